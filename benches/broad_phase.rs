@@ -0,0 +1,52 @@
+//! Fixed steps simulated per second by the headless simulation as the population grows.
+//! Ray casting and collision checks dominate each step, so the numbers follow how well
+//! the `BroadPhase` narrows down the colliders tested by every car. Every population is
+//! run twice, once with the sweep and once testing every collider from every car.
+//!
+//! Run with `cargo bench --bench broad_phase`
+
+use bevy::prelude::*;
+use selfdriving_car::{BroadPhase, Config, SelfDrivingCar, WindowSize};
+use std::time::Instant;
+
+const STEPS: u32 = 600;
+
+/// Fixed steps per second simulated by `cars` cars with the given broad phase
+fn steps_per_second(cars: u16, broad_phase: BroadPhase) -> f64 {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(WindowSize(400., 600.))
+        .insert_resource(broad_phase)
+        .add_plugins(SelfDrivingCar {
+            config: Config {
+                controlllable_cars: cars,
+                seed: Some(0),
+                ..default()
+            },
+            headless: true,
+            ..default()
+        });
+    // Startup
+    app.update();
+
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        app.update();
+    }
+    f64::from(STEPS) / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    println!(
+        "{:>6} {:>14} {:>14} {:>9}",
+        "cars", "sweep steps/s", "brute steps/s", "speed-up"
+    );
+    for cars in [250, 1000, 2000, 4000] {
+        let sweep = steps_per_second(cars, BroadPhase::default());
+        let brute = steps_per_second(cars, BroadPhase::exhaustive());
+        println!(
+            "{cars:>6} {sweep:>14.1} {brute:>14.1} {:>8.2}x",
+            sweep / brute
+        );
+    }
+}
@@ -0,0 +1,438 @@
+//! On-disk layout of the saved brains. Every change to the layout bumps [`VERSION`]
+//! and keeps a way of reading the previous layouts.
+
+mod binary;
+mod json;
+
+pub(super) use binary::{HEADER_LEN, MAGIC};
+
+pub use json::SCHEMA_VERSION as JSON_SCHEMA_VERSION;
+
+use super::{utc_date, Brain, BrainEncoding, BrainMetadata, PersistenceError};
+use crate::components::{Activation, NetworkLevel};
+use crate::resources::NetworkConfig;
+use serde::{Deserialize, Serialize};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct BrainFile {
+    version: u32,
+    metadata: FileMetadata,
+    topology: Topology,
+    sensors: Sensors,
+    levels: Vec<Level>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileMetadata {
+    generation: u32,
+    fitness: f32,
+    distance: f32,
+    seed: u64,
+    timestamp: u64,
+    date: String,
+    mutate_factor: f32,
+}
+
+/// Neurons of every layer, from the inputs to the outputs
+#[derive(Serialize, Deserialize)]
+struct Topology {
+    layers: Vec<u8>,
+}
+
+/// Rays feeding the input layer, evenly spread around the front of the car
+#[derive(Serialize, Deserialize)]
+struct Sensors {
+    rays: u8,
+    ray_length: f32,
+    ray_spread: f32,
+}
+
+/// Weights from every input to every output of a level and the bias of every output,
+/// without the buffers only used while driving
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Level {
+    activation: Activation,
+    weights: Vec<Vec<f32>>,
+    biases: Vec<f32>,
+}
+
+impl From<&Brain> for BrainFile {
+    fn from(brain: &Brain) -> Self {
+        let metadata = &brain.metadata;
+        let mut layers: Vec<u8> = brain
+            .levels
+            .iter()
+            .map(|level| level.weights.len() as u8)
+            .collect();
+        layers.extend(brain.levels.last().map(|level| level.biases.len() as u8));
+        Self {
+            version: VERSION,
+            metadata: FileMetadata {
+                generation: metadata.generation,
+                fitness: metadata.fitness,
+                distance: metadata.distance,
+                seed: metadata.seed,
+                timestamp: metadata.timestamp,
+                date: metadata.date.clone(),
+                mutate_factor: metadata.network.mutate_factor,
+            },
+            sensors: Sensors {
+                rays: layers.first().copied().unwrap_or_default(),
+                ray_length: metadata.network.input_ray_length,
+                ray_spread: metadata.network.input_ray_spread,
+            },
+            topology: Topology { layers },
+            levels: brain.levels.iter().map(Level::from).collect(),
+        }
+    }
+}
+
+impl BrainFile {
+    fn into_brain(self) -> Result<Brain, PersistenceError> {
+        let layers = &self.topology.layers;
+        if layers.len() < 2 || self.levels.len() != layers.len() - 1 {
+            return Err(PersistenceError::Topology(format!(
+                "{} layers can't be connected by {} levels",
+                layers.len(),
+                self.levels.len()
+            )));
+        }
+        let inputs = self.levels[0].weights.len();
+        if usize::from(self.sensors.rays) != inputs {
+            return Err(PersistenceError::InputMismatch {
+                rays: self.sensors.rays.into(),
+                inputs,
+            });
+        }
+        let levels: Vec<NetworkLevel> = self.levels.into_iter().map(Level::into).collect();
+        let network = NetworkConfig {
+            input_ray_length: self.sensors.ray_length,
+            input_ray_spread: self.sensors.ray_spread,
+            mutate_factor: self.metadata.mutate_factor,
+            ..network_config(&levels)
+        };
+        let metadata = self.metadata;
+        Ok(Brain {
+            metadata: BrainMetadata {
+                generation: metadata.generation,
+                fitness: metadata.fitness,
+                distance: metadata.distance,
+                seed: metadata.seed,
+                timestamp: metadata.timestamp,
+                date: metadata.date,
+                network,
+            },
+            levels,
+        })
+    }
+}
+
+impl From<&NetworkLevel> for Level {
+    fn from(level: &NetworkLevel) -> Self {
+        Level {
+            activation: level.activation,
+            weights: level.weights.clone(),
+            biases: level.biases.clone(),
+        }
+    }
+}
+
+impl From<Level> for NetworkLevel {
+    fn from(level: Level) -> Self {
+        NetworkLevel {
+            inputs: vec![0.; level.weights.len()],
+            outputs: vec![0.; level.biases.len()],
+            weights: level.weights,
+            biases: level.biases,
+            activation: level.activation,
+        }
+    }
+}
+
+/// Shape and activations of the network made of `levels`, the sensors and the mutation
+/// keep their defaults
+fn network_config(levels: &[NetworkLevel]) -> NetworkConfig {
+    let default = NetworkConfig::default();
+    let (Some(first), Some(last)) = (levels.first(), levels.last()) else {
+        return default;
+    };
+    NetworkConfig {
+        hidden_activation: first.activation,
+        // Counts the output level too, as `NetworkConfig` does
+        hidden_layers: levels.len() as u8,
+        hidden_layers_neuron_count: if levels.len() > 1 {
+            first.biases.len() as u8
+        } else {
+            default.hidden_layers_neuron_count
+        },
+        input_neuron_count: first.weights.len() as u8,
+        output_activation: last.activation,
+        output_neuron_count: last.biases.len() as u8,
+        ..default
+    }
+}
+
+/// Serializes the brain in the current layout
+pub(super) fn encode(brain: &Brain, encoding: BrainEncoding) -> Result<Vec<u8>, PersistenceError> {
+    let file = BrainFile::from(brain);
+    match encoding {
+        BrainEncoding::Ron => ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+            .map(String::into_bytes)
+            .map_err(|e| PersistenceError::Serialize(e.to_string())),
+        BrainEncoding::Binary => Ok(binary::encode(&file)),
+        BrainEncoding::Json => json::encode(file),
+    }
+}
+
+/// Reads a brain in any encoding, telling them apart by the binary magic number and by
+/// JSON being the only one starting with an object
+pub(super) fn decode(bytes: &[u8]) -> Result<Brain, PersistenceError> {
+    if bytes.starts_with(binary::MAGIC) {
+        return binary::decode(bytes).and_then(BrainFile::into_brain);
+    }
+    let contents = std::str::from_utf8(bytes)
+        .map_err(|_| PersistenceError::Parse("neither a text nor a binary brain".to_string()))?;
+    if contents.trim_start().starts_with('{') {
+        return json::decode(bytes).and_then(BrainFile::into_brain);
+    }
+    parse(contents)
+}
+
+/// Generation, fitness and save time of a brain, read without its levels
+pub(super) struct Header {
+    pub generation: u32,
+    pub fitness: f32,
+    pub timestamp: u64,
+}
+
+impl From<FileMetadata> for Header {
+    fn from(metadata: FileMetadata) -> Self {
+        Header {
+            generation: metadata.generation,
+            fitness: metadata.fitness,
+            timestamp: metadata.timestamp,
+        }
+    }
+}
+
+/// Reads the header of a brain in any encoding, the levels are only checked by [`decode`].
+/// Binary brains only need their first [`HEADER_LEN`] bytes.
+pub(super) fn decode_header(bytes: &[u8]) -> Result<Header, PersistenceError> {
+    if bytes.starts_with(binary::MAGIC) {
+        return binary::decode_header(bytes).map(Header::from);
+    }
+    let contents = std::str::from_utf8(bytes)
+        .map_err(|_| PersistenceError::Parse("neither a text nor a binary brain".to_string()))?;
+    if contents.trim_start().starts_with('{') {
+        return json::decode_header(bytes).map(Header::from);
+    }
+
+    #[derive(Deserialize)]
+    struct VersionedHeader {
+        metadata: FileMetadata,
+    }
+    #[derive(Deserialize)]
+    struct UnversionedHeader {
+        metadata: UnversionedMetadata,
+    }
+
+    match ron::from_str::<Probe>(contents) {
+        Ok(Probe { version: VERSION }) => ron::from_str::<VersionedHeader>(contents)
+            .map(|header| header.metadata.into())
+            .map_err(parse_error),
+        Ok(Probe { version: 0 }) => ron::from_str::<UnversionedHeader>(contents)
+            .map(|UnversionedHeader { metadata }| Header {
+                generation: metadata.generation,
+                fitness: metadata.fitness,
+                timestamp: metadata.timestamp,
+            })
+            .map_err(parse_error),
+        Ok(Probe { version }) => Err(PersistenceError::UnsupportedVersion(version)),
+        // The first layout has no header, only the levels tell it's a brain
+        Err(_) => parse(contents).map(|brain| Header {
+            generation: brain.metadata.generation,
+            fitness: brain.metadata.fitness,
+            timestamp: brain.metadata.timestamp,
+        }),
+    }
+}
+
+/// Versions start at 1, files without one were saved before brains were versioned
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    version: u32,
+}
+
+/// Reads any RON layout ever written, migrating older ones to the current [`Brain`]
+fn parse(contents: &str) -> Result<Brain, PersistenceError> {
+    match ron::from_str::<Probe>(contents) {
+        Ok(Probe { version: VERSION }) => ron::from_str::<BrainFile>(contents)
+            .map_err(parse_error)
+            .and_then(BrainFile::into_brain),
+        Ok(Probe { version: 0 }) => ron::from_str::<UnversionedFile>(contents)
+            .map(UnversionedFile::into_brain)
+            .map_err(parse_error),
+        Ok(Probe { version }) => Err(PersistenceError::UnsupportedVersion(version)),
+        Err(_) => ron::from_str::<Vec<ReflectedLevel>>(contents)
+            .map(|levels| reflected_levels_into_brain(contents, levels))
+            .map_err(parse_error),
+    }
+}
+
+/// Layout written before brains were versioned: a metadata header along with the
+/// reflected network levels
+#[derive(Deserialize)]
+struct UnversionedFile {
+    metadata: UnversionedMetadata,
+    levels: Vec<ReflectedLevel>,
+}
+
+#[derive(Deserialize)]
+struct UnversionedMetadata {
+    generation: u32,
+    fitness: f32,
+    distance: f32,
+    seed: u64,
+    timestamp: u64,
+    date: String,
+    network: NetworkConfig,
+}
+
+/// A reflected [`NetworkLevel`], its runtime buffers are skipped
+#[derive(Deserialize)]
+struct ReflectedLevel {
+    weights: Vec<Vec<f32>>,
+    biases: Vec<f32>,
+    /// Missing from the brains saved before activations were configurable
+    #[serde(default)]
+    activation: Activation,
+}
+
+impl From<ReflectedLevel> for NetworkLevel {
+    fn from(level: ReflectedLevel) -> Self {
+        Level {
+            activation: level.activation,
+            weights: level.weights,
+            biases: level.biases,
+        }
+        .into()
+    }
+}
+
+impl UnversionedFile {
+    fn into_brain(self) -> Brain {
+        let metadata = self.metadata;
+        let levels: Vec<NetworkLevel> = self.levels.into_iter().map(Into::into).collect();
+        Brain {
+            metadata: BrainMetadata {
+                generation: metadata.generation,
+                fitness: metadata.fitness,
+                distance: metadata.distance,
+                seed: metadata.seed,
+                timestamp: metadata.timestamp,
+                date: metadata.date,
+                // The stored config could disagree with the levels, which are what drives
+                network: NetworkConfig {
+                    input_ray_length: metadata.network.input_ray_length,
+                    input_ray_spread: metadata.network.input_ray_spread,
+                    mutate_factor: metadata.network.mutate_factor,
+                    ..network_config(&levels)
+                },
+            },
+            levels,
+        }
+    }
+}
+
+/// The first layout, a bare list of reflected levels preceded by a `// seed: N` comment.
+/// Nothing else was stored so the sensors are assumed to be the default ones.
+fn reflected_levels_into_brain(contents: &str, levels: Vec<ReflectedLevel>) -> Brain {
+    let seed = contents
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("// seed: "))
+        .and_then(|seed| seed.trim().parse().ok())
+        .unwrap_or_default();
+    let levels: Vec<NetworkLevel> = levels.into_iter().map(Into::into).collect();
+    Brain {
+        metadata: BrainMetadata {
+            generation: 0,
+            fitness: 0.,
+            distance: 0.,
+            seed,
+            timestamp: 0,
+            date: utc_date(0, "-", ":", "T"),
+            network: network_config(&levels),
+        },
+        levels,
+    }
+}
+
+fn parse_error(e: ron::error::SpannedError) -> PersistenceError {
+    PersistenceError::Parse(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::read_brain;
+
+    /// Brain saved by the first version of the simulation: the reflected levels, with their
+    /// runtime buffers and without activations
+    const BASELINE_BRAIN: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/baseline_brain.ron"
+    );
+
+    #[test]
+    fn migrates_the_baseline_brain() {
+        let brain = read_brain(BASELINE_BRAIN).unwrap();
+
+        let layers: Vec<(usize, usize)> = brain
+            .levels
+            .iter()
+            .map(|level| (level.weights.len(), level.biases.len()))
+            .collect();
+        assert_eq!(layers, [(3, 2), (2, 4)]);
+        assert!(brain
+            .levels
+            .iter()
+            .all(|level| level.activation == Activation::Step));
+        assert_eq!(
+            brain.levels[0].weights,
+            [[0.5, -0.25], [-0.75, 0.125], [0.0625, 1.]]
+        );
+        assert_eq!(brain.levels[1].biases, [0.5, -0.25, 0.625, -0.875]);
+        // The buffers are sized for the levels, whatever the car was sensing when saved
+        assert_eq!(brain.levels[0].inputs, [0.; 3]);
+        assert_eq!(brain.levels[1].outputs, [0.; 4]);
+
+        let default = NetworkConfig::default();
+        let network = &brain.metadata.network;
+        assert_eq!(
+            (
+                network.input_neuron_count,
+                network.hidden_layers,
+                network.hidden_layers_neuron_count,
+                network.output_neuron_count
+            ),
+            (3, 2, 2, 4)
+        );
+        assert_eq!(network.hidden_activation, Activation::Step);
+        assert_eq!(network.output_activation, Activation::Step);
+        assert_eq!(network.input_ray_length, default.input_ray_length);
+        assert_eq!(network.input_ray_spread, default.input_ray_spread);
+        assert_eq!(brain.metadata.seed, 0);
+    }
+
+    #[test]
+    fn reads_the_seed_comment_of_bare_levels() {
+        let contents = std::fs::read_to_string(BASELINE_BRAIN).unwrap();
+        let brain = decode(format!("// seed: 42\n{contents}").as_bytes()).unwrap();
+        assert_eq!(brain.metadata.seed, 42);
+        assert_eq!(brain.levels.len(), 2);
+    }
+}
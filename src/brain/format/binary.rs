@@ -0,0 +1,313 @@
+//! Compact layout of [`BrainFile`], for archiving many brains. Every number is little-endian
+//! and the file ends with the CRC-32 of everything before it:
+//!
+//! | Field                                   | Type                           |
+//! |-----------------------------------------|--------------------------------|
+//! | magic                                   | `b"SDCB"`                      |
+//! | version                                 | `u32`                          |
+//! | generation                              | `u32`                          |
+//! | fitness, distance                       | `f32`                          |
+//! | seed, timestamp                         | `u64`                          |
+//! | mutate factor                           | `f32`                          |
+//! | rays                                    | `u8`                           |
+//! | ray length, ray spread                  | `f32`                          |
+//! | layer count, then neurons of each layer | `u8`                           |
+//! | for each level: activation              | `u8`                           |
+//! | weights, one row per input              | `f32`                          |
+//! | biases                                  | `f32`                          |
+//! | checksum                                | `u32`                          |
+
+use super::{BrainFile, FileMetadata, Level, Sensors, Topology, VERSION};
+use crate::brain::{utc_date, PersistenceError};
+use crate::components::Activation;
+
+pub(in crate::brain) const MAGIC: &[u8; 4] = b"SDCB";
+/// Bytes from the magic number to the mutate factor
+pub(in crate::brain) const HEADER_LEN: usize = 40;
+
+/// Activations by their code in the file, new ones go at the end
+const ACTIVATIONS: [Activation; 7] = [
+    Activation::Step,
+    Activation::Sigmoid,
+    Activation::Tanh,
+    Activation::Relu,
+    Activation::LeakyRelu,
+    Activation::Linear,
+    Activation::Softmax,
+];
+
+pub(super) fn encode(file: &BrainFile) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(file.version.to_le_bytes());
+    let metadata = &file.metadata;
+    bytes.extend(metadata.generation.to_le_bytes());
+    bytes.extend(metadata.fitness.to_le_bytes());
+    bytes.extend(metadata.distance.to_le_bytes());
+    bytes.extend(metadata.seed.to_le_bytes());
+    bytes.extend(metadata.timestamp.to_le_bytes());
+    bytes.extend(metadata.mutate_factor.to_le_bytes());
+    bytes.push(file.sensors.rays);
+    bytes.extend(file.sensors.ray_length.to_le_bytes());
+    bytes.extend(file.sensors.ray_spread.to_le_bytes());
+    bytes.push(file.topology.layers.len() as u8);
+    bytes.extend(&file.topology.layers);
+    for level in &file.levels {
+        let activation = ACTIVATIONS.iter().position(|a| *a == level.activation);
+        bytes.push(activation.unwrap_or_default() as u8);
+        for value in level.weights.iter().flatten().chain(&level.biases) {
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+    bytes.extend(crc32(&bytes).to_le_bytes());
+    bytes
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<BrainFile, PersistenceError> {
+    let Some((contents, checksum)) = bytes.split_last_chunk::<4>() else {
+        return Err(truncated());
+    };
+    let (expected, found) = (u32::from_le_bytes(*checksum), crc32(contents));
+    if expected != found {
+        return Err(PersistenceError::Checksum { expected, found });
+    }
+
+    let mut reader = Reader(contents);
+    let metadata = read_header(&mut reader)?;
+    let sensors = Sensors {
+        rays: reader.u8()?,
+        ray_length: reader.f32()?,
+        ray_spread: reader.f32()?,
+    };
+    let layer_count = reader.u8()?;
+    let layers = reader.take(layer_count.into())?.to_vec();
+    let mut levels = Vec::new();
+    for pair in layers.windows(2) {
+        let (inputs, outputs) = (usize::from(pair[0]), usize::from(pair[1]));
+        let activation = *ACTIVATIONS
+            .get(usize::from(reader.u8()?))
+            .ok_or_else(|| PersistenceError::Parse("unknown activation".to_string()))?;
+        let weights = (0..inputs)
+            .map(|_| (0..outputs).map(|_| reader.f32()).collect())
+            .collect::<Result<_, _>>()?;
+        let biases = (0..outputs)
+            .map(|_| reader.f32())
+            .collect::<Result<_, _>>()?;
+        levels.push(Level {
+            activation,
+            weights,
+            biases,
+        });
+    }
+    if !reader.0.is_empty() {
+        return Err(PersistenceError::Parse(format!(
+            "{} unexpected bytes after the last level",
+            reader.0.len()
+        )));
+    }
+
+    Ok(BrainFile {
+        version: VERSION,
+        metadata,
+        topology: Topology { layers },
+        sensors,
+        levels,
+    })
+}
+
+/// Reads the metadata from the first [`HEADER_LEN`] bytes, without checking the levels
+/// or the checksum
+pub(super) fn decode_header(bytes: &[u8]) -> Result<FileMetadata, PersistenceError> {
+    read_header(&mut Reader(bytes))
+}
+
+fn read_header(reader: &mut Reader) -> Result<FileMetadata, PersistenceError> {
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(PersistenceError::Parse("not a binary brain".to_string()));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(PersistenceError::UnsupportedVersion(version));
+    }
+    let generation = reader.u32()?;
+    let fitness = reader.f32()?;
+    let distance = reader.f32()?;
+    let seed = reader.u64()?;
+    let timestamp = reader.u64()?;
+    Ok(FileMetadata {
+        generation,
+        fitness,
+        distance,
+        seed,
+        timestamp,
+        date: utc_date(timestamp, "-", ":", "T"),
+        mutate_factor: reader.f32()?,
+    })
+}
+
+/// Bytes left to decode
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], PersistenceError> {
+        if self.0.len() < count {
+            return Err(truncated());
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PersistenceError> {
+        Ok(self.take(N)?.try_into().expect("took exactly N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, PersistenceError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, PersistenceError> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, PersistenceError> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, PersistenceError> {
+        self.array().map(f32::from_le_bytes)
+    }
+}
+
+fn truncated() -> PersistenceError {
+    PersistenceError::Parse("the binary brain is truncated".to_string())
+}
+
+/// CRC-32 as used by zip and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, byte| {
+        (0..8).fold(crc ^ u32::from(*byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & 0u32.wrapping_sub(crc & 1))
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::format::{decode as decode_brain, encode as encode_brain};
+    use crate::brain::{Brain, BrainEncoding, BrainMetadata};
+    use crate::components::NetworkLevel;
+    use crate::resources::NetworkConfig;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn brain() -> Brain {
+        let mut rng = StdRng::seed_from_u64(3);
+        let network = NetworkConfig {
+            hidden_activation: Activation::Tanh,
+            hidden_layers: 2,
+            hidden_layers_neuron_count: 6,
+            input_neuron_count: 5,
+            input_ray_length: 97.5,
+            input_ray_spread: 1.25,
+            mutate_factor: 0.3,
+            output_activation: Activation::Sigmoid,
+            output_neuron_count: 4,
+        };
+        Brain {
+            metadata: BrainMetadata::now(17, 1234.567, 987.125, 42, network),
+            levels: vec![
+                NetworkLevel::new(5, 6, Activation::Tanh, &mut rng),
+                NetworkLevel::new(6, 4, Activation::Sigmoid, &mut rng),
+            ],
+        }
+    }
+
+    fn assert_same(a: &Brain, b: &Brain) {
+        // Debug prints every float exactly, so equal strings mean equal values
+        assert_eq!(format!("{:?}", a.metadata), format!("{:?}", b.metadata));
+        assert_eq!(a.levels.len(), b.levels.len());
+        for (a, b) in a.levels.iter().zip(&b.levels) {
+            assert_eq!(a.weights, b.weights);
+            assert_eq!(a.biases, b.biases);
+            assert_eq!(a.activation, b.activation);
+        }
+    }
+
+    fn round_trip(brain: &Brain, encoding: BrainEncoding) -> Brain {
+        decode_brain(&encode_brain(brain, encoding).unwrap()).unwrap()
+    }
+
+    /// Replaces the checksum of `bytes` by the one of their altered contents
+    fn resign(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.truncate(bytes.len() - 4);
+        let checksum = crc32(&bytes);
+        bytes.extend(checksum.to_le_bytes());
+        bytes
+    }
+
+    fn encoded() -> Vec<u8> {
+        encode_brain(&brain(), BrainEncoding::Binary).unwrap()
+    }
+
+    #[test]
+    fn converts_ron_to_binary_and_back() {
+        let original = brain();
+        let ron_first = round_trip(
+            &round_trip(&original, BrainEncoding::Ron),
+            BrainEncoding::Binary,
+        );
+        assert_same(&original, &ron_first);
+        let binary_first = round_trip(
+            &round_trip(&original, BrainEncoding::Binary),
+            BrainEncoding::Ron,
+        );
+        assert_same(&original, &binary_first);
+    }
+
+    #[test]
+    fn rejects_a_flipped_byte() {
+        let mut bytes = encoded();
+        bytes[60] ^= 0x10;
+        assert!(matches!(
+            decode(&bytes),
+            Err(PersistenceError::Checksum { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let bytes = encoded();
+        assert!(matches!(
+            decode(&bytes[..3]),
+            Err(PersistenceError::Parse(_))
+        ));
+        // Cut in the middle of a level, with a checksum matching what's left
+        let mut cut = bytes[..bytes.len() - 20].to_vec();
+        cut.extend([0; 4]);
+        match decode(&resign(cut)) {
+            Err(PersistenceError::Parse(reason)) => assert!(reason.contains("truncated")),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = encoded();
+        bytes[..4].copy_from_slice(b"SDCX");
+        match decode(&resign(bytes)) {
+            Err(PersistenceError::Parse(reason)) => assert_eq!(reason, "not a binary brain"),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut bytes = encoded();
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            decode(&resign(bytes)),
+            Err(PersistenceError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+//! Portable layout of a brain for tools outside the simulation, such as notebooks. A car
+//! feeds the distance seen by each ray to the input layer, from `-1` when nothing is hit
+//! to `1` when touching, and reads its controls from the 4 outputs: accelerate, turn left,
+//! turn right and brake.
+//!
+//! ```json
+//! {
+//!   "schema": "selfdriving-car/brain",
+//!   "version": 1,
+//!   "metadata": {
+//!     "generation": 12,
+//!     "fitness": 6822.8,
+//!     "distance": 6822.8,
+//!     "seed": 1,
+//!     "timestamp": 1700000000,
+//!     "date": "2023-11-14T22:13:20",
+//!     "mutate_factor": 0.075
+//!   },
+//!   "sensors": {
+//!     "ray_length": 130.0,
+//!     "ray_angles": [1.41, 0.0, -1.41]
+//!   },
+//!   "layers": [3, 9, 4],
+//!   "levels": [
+//!     { "activation": "Tanh", "weights": [[0.1, ...], ...], "biases": [0.0, ...] },
+//!     ...
+//!   ]
+//! }
+//! ```
+//!
+//! - `ray_angles` are in radians from the front of the car, counterclockwise, one per
+//!   input neuron. They must be evenly spread and symmetric, as they are in the simulation.
+//! - `layers` holds the neuron count of every layer, from the inputs to the outputs.
+//! - `levels` connects each layer to the next one: `weights[i][o]` weighs input `i` for
+//!   output `o`, and `biases[o]` is subtracted from the weighted sum of output `o` before
+//!   its activation is applied.
+//! - `activation` is one of `Step`, `Sigmoid`, `Tanh`, `Relu`, `LeakyRelu`, `Linear` or
+//!   `Softmax`. `Step` outputs 1 when the weighted sum exceeds the bias and 0 otherwise.
+
+use super::{BrainFile, FileMetadata, Level, Sensors, Topology, VERSION};
+use crate::brain::PersistenceError;
+use crate::resources::NetworkConfig;
+use serde::{Deserialize, Serialize};
+
+const SCHEMA: &str = "selfdriving-car/brain";
+pub const SCHEMA_VERSION: u32 = 1;
+/// Largest gap allowed between the stored angles and the evenly spread ones, in radians
+const ANGLE_TOLERANCE: f32 = 1e-4;
+
+#[derive(Serialize, Deserialize)]
+struct JsonBrain {
+    schema: String,
+    version: u32,
+    metadata: FileMetadata,
+    sensors: JsonSensors,
+    layers: Vec<u8>,
+    levels: Vec<Level>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonSensors {
+    ray_length: f32,
+    ray_angles: Vec<f32>,
+}
+
+pub(super) fn encode(file: BrainFile) -> Result<Vec<u8>, PersistenceError> {
+    let network = NetworkConfig {
+        input_neuron_count: file.sensors.rays,
+        input_ray_spread: file.sensors.ray_spread,
+        ..NetworkConfig::default()
+    };
+    let brain = JsonBrain {
+        schema: SCHEMA.to_string(),
+        version: SCHEMA_VERSION,
+        metadata: file.metadata,
+        sensors: JsonSensors {
+            ray_length: file.sensors.ray_length,
+            ray_angles: network.ray_angles(),
+        },
+        layers: file.topology.layers,
+        levels: file.levels,
+    };
+    serde_json::to_vec_pretty(&brain).map_err(|e| PersistenceError::Serialize(e.to_string()))
+}
+
+/// The fields coming before the levels
+#[derive(Deserialize)]
+struct JsonHeader {
+    schema: String,
+    version: u32,
+    metadata: FileMetadata,
+}
+
+fn check_schema(schema: &str, version: u32) -> Result<(), PersistenceError> {
+    if schema != SCHEMA {
+        return Err(PersistenceError::Parse(format!(
+            "unknown schema `{schema}`"
+        )));
+    }
+    if version != SCHEMA_VERSION {
+        return Err(PersistenceError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Reads the metadata, skipping the levels without checking them
+pub(super) fn decode_header(bytes: &[u8]) -> Result<FileMetadata, PersistenceError> {
+    let header: JsonHeader =
+        serde_json::from_slice(bytes).map_err(|e| PersistenceError::Parse(e.to_string()))?;
+    check_schema(&header.schema, header.version)?;
+    Ok(header.metadata)
+}
+
+pub(super) fn decode(bytes: &[u8]) -> Result<BrainFile, PersistenceError> {
+    let brain: JsonBrain =
+        serde_json::from_slice(bytes).map_err(|e| PersistenceError::Parse(e.to_string()))?;
+    check_schema(&brain.schema, brain.version)?;
+
+    let angles = brain.sensors.ray_angles;
+    let spread = match (angles.first(), angles.last()) {
+        (Some(first), Some(last)) if angles.len() > 1 => first - last,
+        _ => 0.,
+    };
+    let sensors = NetworkConfig {
+        input_neuron_count: angles.len() as u8,
+        input_ray_spread: spread,
+        ..NetworkConfig::default()
+    };
+    let evenly_spread = angles.len() <= usize::from(u8::MAX)
+        && (sensors.ray_angles().iter())
+            .zip(&angles)
+            .all(|(expected, angle)| (expected - angle).abs() <= ANGLE_TOLERANCE);
+    if !evenly_spread {
+        return Err(PersistenceError::Parse(
+            "the rays must be evenly spread around the front of the car".to_string(),
+        ));
+    }
+
+    Ok(BrainFile {
+        version: VERSION,
+        metadata: brain.metadata,
+        topology: Topology {
+            layers: brain.layers,
+        },
+        sensors: Sensors {
+            rays: sensors.input_neuron_count,
+            ray_length: brain.sensors.ray_length,
+            ray_spread: spread,
+        },
+        levels: brain.levels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::format::encode as encode_brain;
+    use crate::brain::{export_brain, read_brain, Brain, BrainEncoding, BrainMetadata};
+    use crate::components::{Activation, NetworkLevel};
+    use crate::utils::TempDir;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::{json, Value};
+
+    fn brain(rays: u8) -> Brain {
+        let mut rng = StdRng::seed_from_u64(5);
+        let network = NetworkConfig {
+            hidden_activation: Activation::Relu,
+            hidden_layers: 2,
+            hidden_layers_neuron_count: 6,
+            input_neuron_count: rays,
+            input_ray_length: 97.5,
+            input_ray_spread: if rays == 1 { 0. } else { 1.25 },
+            mutate_factor: 0.3,
+            output_activation: Activation::Tanh,
+            output_neuron_count: 4,
+        };
+        Brain {
+            metadata: BrainMetadata::now(17, 1234.567, 987.125, 42, network),
+            levels: vec![
+                NetworkLevel::new(rays, 6, Activation::Relu, &mut rng),
+                NetworkLevel::new(6, 4, Activation::Tanh, &mut rng),
+            ],
+        }
+    }
+
+    fn assert_same(a: &Brain, b: &Brain) {
+        // Debug prints every float exactly, so equal strings mean equal values
+        assert_eq!(format!("{:?}", a.metadata), format!("{:?}", b.metadata));
+        assert_eq!(a.levels.len(), b.levels.len());
+        for (a, b) in a.levels.iter().zip(&b.levels) {
+            assert_eq!(a.weights, b.weights);
+            assert_eq!(a.biases, b.biases);
+            assert_eq!(a.activation, b.activation);
+        }
+    }
+
+    fn encoded() -> Value {
+        serde_json::from_slice(&encode_brain(&brain(5), BrainEncoding::Json).unwrap()).unwrap()
+    }
+
+    fn decode_value(value: &Value) -> Result<(), PersistenceError> {
+        decode(&serde_json::to_vec(value).unwrap()).map(|_| ())
+    }
+
+    #[test]
+    fn converts_ron_to_json_and_back() {
+        let dir = TempDir::new("json");
+        std::fs::create_dir_all(&dir).unwrap();
+        for rays in [5, 1] {
+            let original = brain(rays);
+            export_brain(dir.join("original.ron"), &original).unwrap();
+            let ron = read_brain(dir.join("original.ron")).unwrap();
+            export_brain(dir.join("converted.json"), &ron).unwrap();
+            let json = read_brain(dir.join("converted.json")).unwrap();
+            export_brain(dir.join("converted.ron"), &json).unwrap();
+            assert_same(&original, &read_brain(dir.join("converted.ron")).unwrap());
+        }
+    }
+
+    #[test]
+    fn reads_a_single_centered_ray() {
+        let mut value = encoded();
+        value["sensors"]["ray_angles"] = json!([0.]);
+        value["layers"][0] = json!(1);
+        value["levels"][0]["weights"] = json!([vec![0.5; 6]]);
+        let file = decode(&serde_json::to_vec(&value).unwrap()).unwrap();
+        assert_eq!((file.sensors.rays, file.sensors.ray_spread), (1, 0.));
+
+        value["sensors"]["ray_angles"] = json!([0.3]);
+        assert!(matches!(
+            decode_value(&value),
+            Err(PersistenceError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_schemas() {
+        let mut value = encoded();
+        value["schema"] = json!("selfdriving-car/dataset");
+        match decode_value(&value) {
+            Err(PersistenceError::Parse(reason)) => {
+                assert_eq!(reason, "unknown schema `selfdriving-car/dataset`")
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut value = encoded();
+        value["version"] = json!(SCHEMA_VERSION + 1);
+        assert!(matches!(
+            decode_value(&value),
+            Err(PersistenceError::UnsupportedVersion(version)) if version == SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_uneven_rays() {
+        let mut value = encoded();
+        value["sensors"]["ray_angles"][1] = json!(0.5);
+        match decode_value(&value) {
+            Err(PersistenceError::Parse(reason)) => assert!(reason.contains("evenly spread")),
+            other => panic!("{other:?}"),
+        }
+    }
+}
@@ -0,0 +1,382 @@
+mod format;
+use crate::components::{NetworkError, NetworkLevel, NeuralNetwork};
+use crate::resources::NetworkConfig;
+use crate::utils::create_new_file;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Folder holding every saved brain
+pub const BRAINS_DIR: &str = "assets/brains";
+
+pub(crate) use format::Level;
+pub use format::{JSON_SCHEMA_VERSION, VERSION as BRAIN_FORMAT_VERSION};
+
+/// A saved network along with how it was trained and how well it drove
+#[derive(Debug, Clone)]
+pub struct Brain {
+    pub metadata: BrainMetadata,
+    pub levels: Vec<NetworkLevel>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BrainMetadata {
+    pub generation: u32,
+    pub fitness: f32,
+    /// Forward distance driven by the car when it was saved
+    pub distance: f32,
+    pub seed: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    /// `timestamp` as an UTC date, for people reading the file
+    pub date: String,
+    /// Settings the network was trained with, ray spread and length included
+    pub network: NetworkConfig,
+}
+
+impl BrainMetadata {
+    /// Metadata of a brain saved right now
+    pub fn now(
+        generation: u32,
+        fitness: f32,
+        distance: f32,
+        seed: u64,
+        network: NetworkConfig,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            generation,
+            fitness,
+            distance,
+            seed,
+            timestamp,
+            date: utc_date(timestamp, "-", ":", "T"),
+            network,
+        }
+    }
+
+    /// Name of the file the brain is saved to without its extension,
+    /// `<timestamp>-<fitness>`
+    pub fn file_stem(&self) -> String {
+        format!(
+            "{}-{:.0}",
+            utc_date(self.timestamp, "", "", "-"),
+            self.fitness
+        )
+    }
+}
+
+/// How a brain is written to disk, both are read back the same way
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BrainEncoding {
+    /// Pretty-printed, meant to be read and edited by people
+    #[default]
+    Ron,
+    /// Little-endian floats behind a header and followed by a checksum, for archiving
+    Binary,
+    /// Portable schema documented in `format/json.rs`, for tools outside the simulation
+    Json,
+}
+
+impl BrainEncoding {
+    pub const ALL: [BrainEncoding; 3] = [
+        BrainEncoding::Ron,
+        BrainEncoding::Binary,
+        BrainEncoding::Json,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            BrainEncoding::Ron => "ron",
+            BrainEncoding::Binary => "bin",
+            BrainEncoding::Json => "json",
+        }
+    }
+
+    /// Encoding matching the extension of `path`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?;
+        Self::ALL
+            .into_iter()
+            .find(|encoding| extension == encoding.extension())
+    }
+}
+
+impl fmt::Display for BrainEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrainEncoding::Ron => write!(f, "RON"),
+            BrainEncoding::Binary => write!(f, "BIN"),
+            BrainEncoding::Json => write!(f, "JSON"),
+        }
+    }
+}
+
+/// Which saved brain gets loaded
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum BrainSelection {
+    /// Most recently saved brain of the library
+    #[default]
+    Latest,
+    /// Brain of the library with the highest fitness
+    Best,
+    File(PathBuf),
+}
+
+impl BrainSelection {
+    /// `latest` and `best` select from the library, anything else is a path
+    pub fn from_arg(arg: &Path) -> Self {
+        match arg.to_str() {
+            Some("latest") => BrainSelection::Latest,
+            Some("best") => BrainSelection::Best,
+            _ => BrainSelection::File(arg.to_path_buf()),
+        }
+    }
+
+    /// Path of the selected brain
+    pub fn resolve(&self, dir: impl AsRef<Path>) -> Result<PathBuf, PersistenceError> {
+        let library = list_brains(dir);
+        let path = match self {
+            BrainSelection::Latest => library.into_iter().next().map(|entry| entry.path),
+            BrainSelection::Best => library
+                .into_iter()
+                .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+                .map(|entry| entry.path),
+            BrainSelection::File(path) => Some(path.clone()),
+        };
+        path.ok_or(PersistenceError::EmptyLibrary)
+    }
+}
+
+impl fmt::Display for BrainSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrainSelection::Latest => write!(f, "Latest"),
+            BrainSelection::Best => write!(f, "Best"),
+            BrainSelection::File(path) => {
+                let name = path.file_stem().unwrap_or(path.as_os_str());
+                write!(f, "{}", name.to_string_lossy())
+            }
+        }
+    }
+}
+
+/// A brain found in the library, described by the header of its file
+#[derive(Debug, Clone)]
+pub struct BrainEntry {
+    pub path: PathBuf,
+    pub generation: u32,
+    pub fitness: f32,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl BrainEntry {
+    /// Reads the header of the brain at `path`, its levels are only checked by [`read_brain`]
+    fn read(path: PathBuf) -> Result<Self, PersistenceError> {
+        let io_error = |e| PersistenceError::Io(path.clone(), e);
+        let mut file = File::open(&path).map_err(io_error)?;
+        let mut bytes = Vec::new();
+        (&mut file)
+            .take(format::HEADER_LEN as u64)
+            .read_to_end(&mut bytes)
+            .map_err(io_error)?;
+        // Text brains can't be read without their levels, the header only has to be parsed
+        if !bytes.starts_with(format::MAGIC) {
+            file.read_to_end(&mut bytes).map_err(io_error)?;
+        }
+        let header = format::decode_header(&bytes)?;
+        Ok(BrainEntry {
+            path,
+            generation: header.generation,
+            fitness: header.fitness,
+            timestamp: header.timestamp,
+        })
+    }
+}
+
+/// Every brain inside `dir` with a readable header, most recent first
+pub fn list_brains(dir: impl AsRef<Path>) -> Vec<BrainEntry> {
+    let Ok(files) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut library: Vec<BrainEntry> = files
+        .filter_map(|file| file.ok().map(|file| file.path()))
+        .filter(|path| BrainEncoding::from_path(path).is_some())
+        .filter_map(|path| BrainEntry::read(path).ok())
+        .collect();
+    library.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp));
+    library
+}
+
+/// Reasons a brain couldn't be saved or loaded
+#[derive(Debug)]
+pub enum PersistenceError {
+    Io(PathBuf, std::io::Error),
+    Serialize(String),
+    /// The file isn't a brain, or is a corrupted one
+    Parse(String),
+    UnsupportedVersion(u32),
+    /// The rays of the brain don't match the neurons of its input layer
+    InputMismatch {
+        rays: usize,
+        inputs: usize,
+    },
+    /// The topology of the file doesn't describe its levels
+    Topology(String),
+    /// The binary brain doesn't hold the data it was written with
+    Checksum {
+        expected: u32,
+        found: u32,
+    },
+    /// The levels can't form a working network
+    InvalidNetwork(NetworkError),
+    /// The extension of the file is none of the [`BrainEncoding`] ones
+    UnknownEncoding(PathBuf),
+    /// There is no saved brain to pick the latest or the best one from
+    EmptyLibrary,
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistenceError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            PersistenceError::Serialize(e) => write!(f, "can't serialize the brain: {e}"),
+            PersistenceError::Parse(e) => write!(f, "not a valid brain: {e}"),
+            PersistenceError::UnsupportedVersion(version) => write!(
+                f,
+                "brain format version {version} is newer than the supported version {}",
+                format::VERSION
+            ),
+            PersistenceError::InputMismatch { rays, inputs } => {
+                write!(f, "the brain has {rays} rays for {inputs} input neurons")
+            }
+            PersistenceError::Topology(reason) => write!(f, "invalid topology: {reason}"),
+            PersistenceError::Checksum { expected, found } => write!(
+                f,
+                "the brain is corrupted, its checksum is {found:08x} instead of {expected:08x}"
+            ),
+            PersistenceError::InvalidNetwork(e) => write!(f, "invalid network: {e}"),
+            PersistenceError::UnknownEncoding(path) => write!(
+                f,
+                "{}: the extension must be .ron, .bin or .json",
+                path.display()
+            ),
+            PersistenceError::EmptyLibrary => write!(f, "no brain saved in {BRAINS_DIR}"),
+        }
+    }
+}
+
+impl std::error::Error for PersistenceError {}
+
+/// Reads a brain saved with [`write_brain`], or with any earlier version of the format
+pub fn read_brain(path: impl AsRef<Path>) -> Result<Brain, PersistenceError> {
+    let path = path.as_ref();
+    let brain_serialized =
+        std::fs::read(path).map_err(|e| PersistenceError::Io(path.to_path_buf(), e))?;
+    let brain = format::decode(&brain_serialized)?;
+    NeuralNetwork::validate(&brain.levels).map_err(PersistenceError::InvalidNetwork)?;
+    Ok(brain)
+}
+
+/// Writes the brain into `dir` with the given encoding, returning the path of the new file.
+/// Brains saved in the same second with the same fitness get a numbered suffix.
+pub fn write_brain(
+    dir: impl AsRef<Path>,
+    brain: &Brain,
+    encoding: BrainEncoding,
+) -> Result<PathBuf, PersistenceError> {
+    let dir = dir.as_ref();
+    let (stem, extension) = (brain.metadata.file_stem(), encoding.extension());
+    let brain_serialized = format::encode(brain, encoding)?;
+    let (path, mut file) = create_new_file(dir, &stem, extension)
+        .map_err(|e| PersistenceError::Io(dir.join(format!("{stem}.{extension}")), e))?;
+    file.write_all(&brain_serialized)
+        .map_err(|e| PersistenceError::Io(path.clone(), e))?;
+    Ok(path)
+}
+
+/// Writes the brain to `path`, encoded as its extension tells
+pub fn export_brain(path: impl AsRef<Path>, brain: &Brain) -> Result<(), PersistenceError> {
+    let path = path.as_ref();
+    let encoding = BrainEncoding::from_path(path)
+        .ok_or_else(|| PersistenceError::UnknownEncoding(path.to_path_buf()))?;
+    let brain_serialized = format::encode(brain, encoding)?;
+    std::fs::write(path, brain_serialized).map_err(|e| PersistenceError::Io(path.to_path_buf(), e))
+}
+
+/// Formats seconds since the Unix epoch as an UTC date with the given separators
+/// between the date parts, the time parts and the date and the time
+pub(super) fn utc_date(timestamp: u64, date_sep: &str, time_sep: &str, sep: &str) -> String {
+    let (days, secs) = (timestamp / 86_400, timestamp % 86_400);
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{date_sep}{month:02}{date_sep}{day:02}{sep}{:02}{time_sep}{:02}{time_sep}{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Activation;
+    use crate::utils::TempDir;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn brain(fitness: f32) -> Brain {
+        let mut rng = StdRng::seed_from_u64(1);
+        let network = NetworkConfig {
+            input_neuron_count: 3,
+            hidden_layers: 1,
+            ..NetworkConfig::default()
+        };
+        Brain {
+            metadata: BrainMetadata::now(4, fitness, fitness, 1, network),
+            levels: vec![NetworkLevel::new(3, 4, Activation::Step, &mut rng)],
+        }
+    }
+
+    #[test]
+    fn brains_saved_together_keep_their_own_file() {
+        let dir = TempDir::new("brains");
+        let mut paths = Vec::new();
+        for encoding in BrainEncoding::ALL {
+            paths.push(write_brain(&dir, &brain(0.), encoding).unwrap());
+            paths.push(write_brain(&dir, &brain(0.), encoding).unwrap());
+        }
+        paths.push(write_brain(&dir, &brain(12.), BrainEncoding::Binary).unwrap());
+
+        let mut listed: Vec<PathBuf> = list_brains(&dir).into_iter().map(|e| e.path).collect();
+        listed.sort();
+        paths.sort();
+        assert_eq!(listed, paths);
+        for path in &paths {
+            read_brain(path).unwrap();
+        }
+        assert_eq!(
+            BrainSelection::Best.resolve(&dir).unwrap().extension(),
+            Some("bin".as_ref())
+        );
+        let best = list_brains(&dir)
+            .into_iter()
+            .find(|entry| entry.fitness == 12.)
+            .unwrap();
+        assert_eq!((best.generation, best.timestamp > 0), (4, true));
+    }
+}
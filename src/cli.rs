@@ -0,0 +1,496 @@
+use selfdriving_car::{
+    Activation, ConfigFile, NetworkConfig, Optimizer, RunMode, SelfDrivingCar, TrainingConfig,
+    WindowSize, DATASETS_DIR,
+};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Configuration loaded when `--config` is omitted and the file exists
+const DEFAULT_CONFIG: &str = "config.ron";
+
+pub const USAGE: &str = "Usage: selfdriving-car [COMMAND] [OPTIONS]
+
+Commands:
+  train                    Evolve a population of cars (default)
+  watch <BRAIN>            Drive a saved brain without evolving it
+  evaluate <BRAIN>         Drive a saved brain for several episodes and report its fitness
+  replay <REPLAY>          Play back a generation recorded with --replays
+  convert <BRAIN> <OUTPUT> Write a saved brain to OUTPUT, encoded as its extension tells:
+                           .ron, .bin (compact binary) or .json (portable schema)
+  fit [DATASET]            Train a network by backpropagation on recorded driving and save it
+                           to assets/brains, DATASET being a session file or a folder of them
+                           [default: assets/demonstrations]
+
+A <BRAIN> is the path of a saved brain, or `latest` and `best` to pick one from assets/brains.
+
+Options:
+  --config <PATH>          RON file with the simulation, network and road settings, overridden
+                           by the options below and reloaded while training [default: config.ron]
+  --history <PATH>         CSV file rewritten with the fitness of every generation
+  --replays <DIR>          Record every generation as a replay file in DIR
+  --episodes <N>           Episodes driven by `evaluate` [default: 10]
+  --seed <N>               Seed of every random value, random when omitted
+  --population <N>         Controllable cars per generation
+  --survivors <N>          Best cars breeding the next generation
+  --traffic <N>            Maximum traffic cars on the road
+  --curviness <FACTOR>     How much the road bends, from 0 (straight) to 1 [default: 0.5]
+  --hidden-layers <N>      Hidden layers of the networks, between the rays and the controls
+  --hidden-neurons <N>     Neurons of every hidden layer
+  --rays <N>               Rays, and input neurons, of every car
+  --ray-length <LENGTH>    Length of the rays
+  --ray-spread <RADIANS>   Angle covered by the rays
+  --mutate <FACTOR>        How much children networks differ from their parents
+  --epochs <N>             Passes over the dataset made by `fit` [default: 50]
+  --batch <N>              Samples of every `fit` update [default: 32]
+  --optimizer <NAME>       Optimizer of `fit`, `sgd` or `adam` [default: adam]
+  --learning-rate <RATE>   Step size of the `fit` optimizer [default: 0.1 with sgd, 0.003
+                           with adam]
+  --from <BRAIN>           Brain `fit` starts from instead of a random network, keeping its
+                           layers and rays
+  --window <WIDTHxHEIGHT>  Window size, also the size of the simulated road [default: 400x600]
+  --headless               Run the simulation without a window, as fast as possible
+  -h, --help               Print this message
+
+Keys:
+  Space                    Pause or resume the simulation
+  .                        Run a single simulation step
+  - and +                  Slow down or speed up the simulation, from 0.25x to 64x
+  M                        Drive a car with the arrow keys, among the networks or alone
+  R                        Record the driving into assets/demonstrations to train networks
+
+Replay keys, along with Space, . and - and +:
+  Left and Right           Seek a second back or forth, ten seconds with Shift
+  Home and End             Jump to the start or the end of the replay
+  Tab                      Follow the next car still driving, the previous one with Shift
+  S                        Save the brain of the followed car into assets/brains";
+
+pub struct Cli {
+    pub plugin: SelfDrivingCar,
+    pub window_size: WindowSize,
+}
+
+pub enum Command {
+    Simulate(Cli),
+    /// Rewrites a brain in another encoding, without running the simulation
+    Convert {
+        brain: PathBuf,
+        output: PathBuf,
+    },
+    /// Trains a network on recorded driving, without running the simulation
+    Fit {
+        dataset: PathBuf,
+        /// Shape of the network, its rays being taken from the dataset
+        network: NetworkConfig,
+        training: TrainingConfig,
+        /// Brain whose network is trained instead of a random one
+        from: Option<PathBuf>,
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Help,
+    Invalid(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Help => write!(f, "{USAGE}"),
+            CliError::Invalid(reason) => write!(f, "{reason}\n\n{USAGE}"),
+        }
+    }
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let args: Vec<String> = args.into_iter().collect();
+    if args.first().is_some_and(|arg| arg == "convert") {
+        return parse_convert(&args[1..]);
+    }
+    if args.first().is_some_and(|arg| arg == "fit") {
+        return parse_fit(&args[1..]);
+    }
+    let config_path = match args.iter().position(|arg| arg == "--config") {
+        Some(idx) => Some(PathBuf::from(required(
+            "--config",
+            args.get(idx + 1).cloned(),
+        )?)),
+        None => Some(PathBuf::from(DEFAULT_CONFIG)).filter(|path| path.exists()),
+    };
+    // The file is applied first so every other option overrides it
+    let file = match &config_path {
+        Some(path) => load_config(path)?,
+        None => ConfigFile::default(),
+    };
+    let mut plugin = SelfDrivingCar {
+        config: file.simulation,
+        network_config: file.network,
+        road: file.road,
+        config_path,
+        ..Default::default()
+    };
+
+    let mut args = args.into_iter().peekable();
+    let mut window_size = WindowSize(400., 600.);
+
+    let command = match args.peek().map(String::as_str) {
+        Some("train") | Some("watch") | Some("evaluate") | Some("replay") => args.next(),
+        _ => None,
+    };
+    let mut episodes = 10;
+
+    while let Some(arg) = args.next() {
+        let config = &mut plugin.config;
+        let network_config = &mut plugin.network_config;
+        match arg.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "--headless" => plugin.headless = true,
+            "--config" => {
+                args.next();
+            }
+            "--history" => plugin.history_path = Some(required(&arg, args.next())?.into()),
+            "--replays" => plugin.replays_dir = Some(required(&arg, args.next())?.into()),
+            "--episodes" => episodes = value(&arg, args.next())?,
+            "--seed" => config.seed = Some(value(&arg, args.next())?),
+            "--population" => config.controlllable_cars = value(&arg, args.next())?,
+            "--survivors" => config.survivors = value(&arg, args.next())?,
+            "--traffic" => config.max_traffic = value(&arg, args.next())?,
+            "--curviness" => config.road_curviness = value(&arg, args.next())?,
+            "--hidden-layers" => network_config.hidden_layers = hidden_layers(&arg, args.next())?,
+            "--hidden-neurons" => {
+                network_config.hidden_layers_neuron_count = value(&arg, args.next())?
+            }
+            "--rays" => network_config.input_neuron_count = value(&arg, args.next())?,
+            "--ray-length" => network_config.input_ray_length = value(&arg, args.next())?,
+            "--ray-spread" => network_config.input_ray_spread = value(&arg, args.next())?,
+            "--mutate" => network_config.mutate_factor = value(&arg, args.next())?,
+            "--window" => {
+                let size = required(&arg, args.next())?;
+                let (width, height) = size.split_once('x').ok_or_else(|| invalid(&arg, &size))?;
+                window_size = WindowSize(
+                    value(&arg, Some(width.to_string()))?,
+                    value(&arg, Some(height.to_string()))?,
+                );
+            }
+            _ if arg.starts_with('-') => {
+                return Err(CliError::Invalid(format!("unknown option `{arg}`")))
+            }
+            _ => match (command.as_deref(), &plugin.mode) {
+                (Some("watch"), RunMode::Train) => plugin.mode = RunMode::Watch(arg.into()),
+                (Some("replay"), RunMode::Train) => plugin.mode = RunMode::Replay(arg.into()),
+                (Some("evaluate"), RunMode::Train) => {
+                    plugin.mode = RunMode::Evaluate {
+                        brain: arg.into(),
+                        episodes,
+                    }
+                }
+                _ => return Err(CliError::Invalid(format!("unexpected argument `{arg}`"))),
+            },
+        }
+    }
+
+    match (command.as_deref(), &mut plugin.mode) {
+        (Some(command @ ("watch" | "evaluate")), RunMode::Train) => {
+            return Err(CliError::Invalid(format!(
+                "`{command}` requires the path of a brain"
+            )))
+        }
+        (Some("replay"), RunMode::Train) => {
+            return Err(CliError::Invalid(
+                "`replay` requires the path of a replay".to_string(),
+            ))
+        }
+        (_, RunMode::Replay(_)) if plugin.headless => {
+            return Err(CliError::Invalid(
+                "`replay` can't run with `--headless`".to_string(),
+            ))
+        }
+        (_, RunMode::Evaluate { .. }) if episodes == 0 => {
+            return Err(CliError::Invalid(
+                "`--episodes` must be at least 1".to_string(),
+            ))
+        }
+        (
+            _,
+            RunMode::Evaluate {
+                episodes: mode_episodes,
+                ..
+            },
+        ) => *mode_episodes = episodes,
+        _ => {}
+    }
+    if plugin.network_config.input_neuron_count == 0 {
+        return Err(CliError::Invalid("`--rays` must be at least 1".to_string()));
+    }
+    if !(0.0..=1.0).contains(&plugin.config.road_curviness) {
+        return Err(CliError::Invalid(
+            "`--curviness` must be between 0 and 1".to_string(),
+        ));
+    }
+    // The options go through the same checks as the file they override
+    let settings = ConfigFile {
+        simulation: plugin.config.clone(),
+        network: plugin.network_config.clone(),
+        road: plugin.road,
+    };
+    settings
+        .validate()
+        .map_err(|e| CliError::Invalid(e.to_string()))?;
+
+    Ok(Command::Simulate(Cli {
+        plugin,
+        window_size,
+    }))
+}
+
+fn parse_convert(args: &[String]) -> Result<Command, CliError> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Err(CliError::Help);
+    }
+    if let Some(arg) = args.iter().find(|arg| arg.starts_with('-')) {
+        return Err(CliError::Invalid(format!("unknown option `{arg}`")));
+    }
+    match args {
+        [brain, output] => Ok(Command::Convert {
+            brain: brain.into(),
+            output: output.into(),
+        }),
+        _ => Err(CliError::Invalid(
+            "`convert` requires the path of a brain and of the output".to_string(),
+        )),
+    }
+}
+
+fn parse_fit(args: &[String]) -> Result<Command, CliError> {
+    let mut dataset = None;
+    // Both activations must have a gradient for backpropagation to follow
+    let mut network = NetworkConfig {
+        hidden_activation: Activation::Tanh,
+        output_activation: Activation::Sigmoid,
+        ..Default::default()
+    };
+    let mut training = TrainingConfig::default();
+    let (mut from, mut seed, mut learning_rate) = (None, None, None);
+    // Last option changing the shape of the network, which `--from` brings instead
+    let mut shape_option = None;
+
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(CliError::Help),
+            "--epochs" => training.epochs = value(&arg, args.next())?,
+            "--batch" => training.batch_size = value(&arg, args.next())?,
+            "--optimizer" => {
+                let name = required(&arg, args.next())?;
+                training.optimizer = match name.as_str() {
+                    "sgd" => Optimizer::Sgd,
+                    "adam" => Optimizer::Adam,
+                    _ => return Err(invalid(&arg, &name)),
+                };
+            }
+            "--learning-rate" => learning_rate = Some(value(&arg, args.next())?),
+            "--hidden-layers" => {
+                network.hidden_layers = hidden_layers(&arg, args.next())?;
+                shape_option = Some(arg);
+            }
+            "--hidden-neurons" => {
+                network.hidden_layers_neuron_count = value(&arg, args.next())?;
+                shape_option = Some(arg);
+            }
+            "--from" => from = Some(required(&arg, args.next())?.into()),
+            "--seed" => seed = Some(value(&arg, args.next())?),
+            _ if arg.starts_with('-') => {
+                return Err(CliError::Invalid(format!("unknown option `{arg}`")))
+            }
+            _ if dataset.is_none() => dataset = Some(PathBuf::from(arg)),
+            _ => return Err(CliError::Invalid(format!("unexpected argument `{arg}`"))),
+        }
+    }
+    if training.batch_size == 0 {
+        return Err(CliError::Invalid(
+            "`--batch` must be at least 1".to_string(),
+        ));
+    }
+    if let (Some(arg), Some(_)) = (&shape_option, &from) {
+        return Err(CliError::Invalid(format!(
+            "`{arg}` can't be combined with `--from`, which keeps the network of the brain"
+        )));
+    }
+    training.learning_rate =
+        learning_rate.unwrap_or_else(|| training.optimizer.default_learning_rate());
+
+    Ok(Command::Fit {
+        dataset: dataset.unwrap_or_else(|| DATASETS_DIR.into()),
+        network,
+        training,
+        from,
+        seed,
+    })
+}
+
+fn load_config(path: &Path) -> Result<ConfigFile, CliError> {
+    ConfigFile::load(path).map_err(|e| CliError::Invalid(format!("{}: {e}", path.display())))
+}
+
+fn required(arg: &str, value: Option<String>) -> Result<String, CliError> {
+    value.ok_or_else(|| CliError::Invalid(format!("`{arg}` requires a value")))
+}
+
+fn value<T: FromStr>(arg: &str, value: Option<String>) -> Result<T, CliError> {
+    let value = required(arg, value)?;
+    value.parse().map_err(|_| invalid(arg, &value))
+}
+
+/// Levels of the networks for `N` hidden layers, which `NetworkConfig` counts along with
+/// the output level
+fn hidden_layers(arg: &str, value: Option<String>) -> Result<u8, CliError> {
+    let value = required(arg, value)?;
+    value
+        .parse::<u8>()
+        .ok()
+        .and_then(|hidden_layers| hidden_layers.checked_add(1))
+        .ok_or_else(|| invalid(arg, &value))
+}
+
+fn invalid(arg: &str, value: &str) -> CliError {
+    CliError::Invalid(format!("invalid value `{value}` for `{arg}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Command, CliError> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    fn simulate(args: &str) -> Cli {
+        match parse_args(args) {
+            Ok(Command::Simulate(cli)) => cli,
+            Ok(_) => panic!("`{args}` doesn't run the simulation"),
+            Err(e) => panic!("`{args}` failed: {e}"),
+        }
+    }
+
+    fn reason(args: &str) -> String {
+        match parse_args(args) {
+            Err(CliError::Invalid(reason)) => reason,
+            Err(CliError::Help) => panic!("`{args}` asked for help"),
+            Ok(_) => panic!("`{args}` was accepted"),
+        }
+    }
+
+    #[test]
+    fn trains_by_default() {
+        assert_eq!(simulate("").plugin.mode, RunMode::Train);
+        assert_eq!(simulate("train --headless").plugin.mode, RunMode::Train);
+        assert!(simulate("--headless").plugin.headless);
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        assert_eq!(
+            simulate("watch best").plugin.mode,
+            RunMode::Watch("best".into())
+        );
+        assert_eq!(
+            simulate("evaluate latest --episodes 3").plugin.mode,
+            RunMode::Evaluate {
+                brain: "latest".into(),
+                episodes: 3
+            }
+        );
+        assert_eq!(
+            simulate("evaluate --episodes 4 latest").plugin.mode,
+            RunMode::Evaluate {
+                brain: "latest".into(),
+                episodes: 4
+            }
+        );
+        assert_eq!(
+            simulate("replay run.ron").plugin.mode,
+            RunMode::Replay("run.ron".into())
+        );
+        assert!(matches!(
+            parse_args("convert a.ron b.json"),
+            Ok(Command::Convert { brain, output })
+                if brain == Path::new("a.ron") && output == Path::new("b.json")
+        ));
+        match parse_args("fit --epochs 3 --seed 7") {
+            Ok(Command::Fit {
+                dataset,
+                training,
+                seed,
+                ..
+            }) => {
+                assert_eq!(dataset, Path::new(DATASETS_DIR));
+                assert_eq!(training.epochs, 3);
+                assert_eq!(training.optimizer, Optimizer::Adam);
+                assert_eq!(seed, Some(7));
+            }
+            _ => panic!("`fit` wasn't parsed"),
+        }
+        match parse_args("fit --optimizer sgd") {
+            Ok(Command::Fit { training, .. }) => {
+                assert_eq!(training.optimizer, Optimizer::Sgd);
+                assert_eq!(training.learning_rate, 0.1);
+            }
+            _ => panic!("`fit --optimizer sgd` wasn't parsed"),
+        }
+    }
+
+    #[test]
+    fn options_override_the_config() {
+        let cli = simulate("--seed 5 --population 20 --survivors 2 --traffic 4 --window 300x500");
+        assert_eq!(cli.plugin.config.seed, Some(5));
+        assert_eq!(cli.plugin.config.controlllable_cars, 20);
+        assert_eq!(cli.plugin.config.survivors, 2);
+        assert_eq!(cli.plugin.config.max_traffic, 4);
+        assert_eq!((cli.window_size.0, cli.window_size.1), (300., 500.));
+        let network = simulate("--ray-spread 1.5").plugin.network_config;
+        assert_eq!(network.input_ray_spread, 1.5);
+    }
+
+    #[test]
+    fn counts_hidden_layers_between_rays_and_controls() {
+        let network = simulate("--hidden-layers 3 --hidden-neurons 5 --rays 7")
+            .plugin
+            .network_config;
+        assert_eq!(network.layers(), vec![7, 5, 5, 5, 4]);
+        let network = simulate("--hidden-layers 0 --rays 7").plugin.network_config;
+        assert_eq!(network.layers(), vec![7, 4]);
+
+        match parse_args("fit --hidden-layers 2") {
+            Ok(Command::Fit { network, .. }) => assert_eq!(network.layers().len(), 4),
+            _ => panic!("`fit` wasn't parsed"),
+        }
+        assert!(reason("--hidden-layers 255").contains("--hidden-layers"));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(reason("watch").contains("requires the path of a brain"));
+        assert!(reason("evaluate").contains("requires the path of a brain"));
+        assert!(reason("replay").contains("requires the path of a replay"));
+        assert!(reason("replay run.ron --headless").contains("--headless"));
+        assert!(reason("evaluate best --episodes 0").contains("--episodes"));
+        assert!(reason("--bogus").contains("unknown option `--bogus`"));
+        assert!(reason("train extra").contains("unexpected argument `extra`"));
+        assert!(reason("--seed").contains("`--seed` requires a value"));
+        assert!(reason("--population many").contains("invalid value `many`"));
+        assert!(reason("--window 300").contains("invalid value `300`"));
+        assert!(reason("--rays 0").contains("--rays"));
+        assert!(reason("--curviness 2").contains("--curviness"));
+        assert!(reason("--population 10 --survivors 11").contains("simulation.survivors"));
+        assert!(reason("--mutate 2").contains("network.mutate_factor"));
+        assert!(reason("convert a.ron").contains("`convert` requires"));
+        assert!(reason("fit --batch 0").contains("--batch"));
+        assert!(reason("fit --optimizer rmsprop").contains("--optimizer"));
+        assert!(reason("fit --from best --hidden-layers 2").contains("`--hidden-layers` can't"));
+        assert!(reason("fit --hidden-neurons 8 --from best").contains("`--hidden-neurons` can't"));
+        assert!(reason("fit --from best --rays 7").contains("unknown option `--rays`"));
+        assert!(matches!(parse_args("--help"), Err(CliError::Help)));
+    }
+}
@@ -1,10 +1,15 @@
-use super::{Controls, StaticCollider};
+use super::{BodySize, CarStats, Controls, FitnessScore, StaticCollider};
+use crate::resources::RoadProperties;
+use crate::road::RoadPath;
 use bevy::prelude::{
     default, Bundle, Color, Component, Sprite, SpriteBundle, Transform, Vec2, Vec3,
 };
 use rand::Rng;
+use std::f32::consts::PI;
 
-#[derive(Component)]
+const CAR_SIZE: Vec2 = Vec2 { x: 30.0, y: 50.0 };
+
+#[derive(Component, Clone)]
 pub struct Car {
     pub acceleration: f32,
     pub friction: f32,
@@ -25,75 +30,134 @@ impl Car {
             speed: 0.0,
         }
     }
+
+    /// Moves the car by one step of `delta` seconds, a car without controls always
+    /// accelerates and never turns
+    pub fn drive(&mut self, transform: &mut Transform, controls: Option<&Controls>, delta: f32) {
+        let mut rotation_factor = 0.;
+        self.accelerate(controls);
+
+        if self.speed != 0. && self.speed.abs() > self.acceleration * 1.5 {
+            self.handling = f32::to_radians(self.max_handling / (self.speed / 100.))
+                .clamp(-PI * 0.66, PI * 0.66);
+            if let Some(control) = controls {
+                rotation_factor = control.turn_direction;
+            }
+        }
+        transform.rotate_z(rotation_factor * self.handling * delta);
+
+        let movement_delta = (transform.rotation * Vec3::Y) * (self.speed * delta);
+        transform.translation += movement_delta;
+    }
+
+    /// Updates the speed for one step, slowed down by the friction
+    fn accelerate(&mut self, controls: Option<&Controls>) {
+        if let Some(control) = controls {
+            self.speed += self.acceleration * control.acceleration;
+        } else {
+            self.speed += self.acceleration;
+        }
+
+        self.speed = match self.speed {
+            speed if speed.abs() < self.friction => 0.,
+            speed if speed < 0. => speed + self.friction,
+            speed if speed > 0. => speed - self.friction,
+            _ => 0.,
+        };
+        self.speed = self.speed.clamp(-self.max_speed * 0.5, self.max_speed);
+    }
 }
 
 #[derive(Bundle)]
 pub struct ControllableCarBundle {
     car: Car,
     controls: Controls,
+    body_size: BodySize,
     sprite: SpriteBundle,
+    stats: CarStats,
+    fitness: FitnessScore,
 }
 
 impl ControllableCarBundle {
-    pub fn new(position: Vec2) -> Self {
+    /// Car at `transform`, its distance being measured from `start_progress` along the road
+    pub fn new(transform: Transform, start_progress: f32) -> Self {
         let car_max_speed = 150.0;
         Self {
             car: Car::new(car_max_speed),
             controls: Controls::default(),
+            body_size: BodySize(CAR_SIZE),
             sprite: SpriteBundle {
                 sprite: Sprite {
                     color: Color::rgba_u8(55, 150, 55, 125),
-                    custom_size: Some(Vec2 { x: 30.0, y: 50.0 }),
-                    ..default()
-                },
-                transform: Transform {
-                    translation: Vec3 {
-                        x: position.x,
-                        y: position.y,
-                        z: 0.0,
-                    },
+                    custom_size: Some(CAR_SIZE),
                     ..default()
                 },
+                transform,
+                ..default()
+            },
+            stats: CarStats {
+                start_progress,
                 ..default()
             },
+            fitness: FitnessScore::default(),
         }
     }
 }
 
-#[derive(Component)]
-pub struct TrafficCar;
+/// Traffic car driving along the center of its lane
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TrafficCar {
+    pub lane: u8,
+    /// Arc length driven along the road
+    pub progress: f32,
+}
+
+impl TrafficCar {
+    /// Moves the car along its lane by one step of `delta` seconds, always accelerating
+    pub fn drive(
+        &mut self,
+        car: &mut Car,
+        transform: &mut Transform,
+        road: RoadProperties,
+        path: &RoadPath,
+        delta: f32,
+    ) {
+        car.accelerate(None);
+        self.progress += car.speed * delta;
+        *transform = road.get_lane_ceter(path, self.lane, self.progress);
+    }
+}
 
 #[derive(Bundle)]
 pub struct TrafficCarBundle {
     car: Car,
+    body_size: BodySize,
     sprite: SpriteBundle,
     collider: StaticCollider,
     traffic_car: TrafficCar,
 }
 
 impl TrafficCarBundle {
-    pub fn new(position_x: f32, position_y: f32) -> Self {
-        let random_speed: f32 = rand::thread_rng().gen_range(60f32..=120f32);
+    pub fn new(traffic_car: TrafficCar, transform: Transform, rng: &mut impl Rng) -> Self {
+        let random_speed: f32 = rng.gen_range(60f32..=120f32);
+        Self::with_speed(traffic_car, transform, random_speed)
+    }
+
+    pub fn with_speed(traffic_car: TrafficCar, transform: Transform, max_speed: f32) -> Self {
         Self {
-            car: Car::new(random_speed),
+            car: Car::new(max_speed),
+            body_size: BodySize(CAR_SIZE),
             sprite: SpriteBundle {
                 sprite: Sprite {
                     color: Color::BEIGE,
-                    custom_size: Some(Vec2 { x: 30.0, y: 50.0 }),
-                    ..default()
-                },
-                transform: Transform {
-                    translation: Vec3 {
-                        x: position_x,
-                        y: position_y,
-                        z: 0.0,
-                    },
+                    custom_size: Some(CAR_SIZE),
                     ..default()
                 },
+                transform,
                 ..default()
             },
             collider: StaticCollider::default(),
-            traffic_car: TrafficCar,
+            traffic_car,
         }
     }
 }
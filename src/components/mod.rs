@@ -1,12 +1,18 @@
 mod car;
 mod network;
 mod ray;
-use bevy::prelude::{Component, Entity};
+use bevy::prelude::{Component, Entity, Timer, Vec2};
+use bevy::utils::HashSet;
 
 pub use car::{Car, ControllableCarBundle, TrafficCar, TrafficCarBundle};
-pub use network::{NetworkLevel, NeuralNetwork};
+pub use network::{
+    Activation, Crossover, NetworkError, NetworkLevel, NeuralNetwork, CONTROL_COUNT,
+};
 pub use ray::{Ray, RayBundle};
 
+/// Size of an entity's body, independent from how it's rendered
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BodySize(pub Vec2);
 #[derive(Component, Default)]
 pub struct StaticCollider {
     pub colliding_with: Vec<Entity>,
@@ -16,14 +22,40 @@ pub struct Controls {
     pub acceleration: f32,
     pub turn_direction: f32,
 }
+/// Driving statistics of a controllable car, used to evaluate its fitness
+#[derive(Component, Default, Debug)]
+pub struct CarStats {
+    /// Arc length along the road of the start position
+    pub start_progress: f32,
+    /// Distance travelled along the road since the start position
+    pub distance: f32,
+    pub time_alive: f32,
+    /// Traffic cars currently behind this car
+    pub overtaken: HashSet<Entity>,
+    /// Sum of the per tick closeness to a lane center
+    pub lane_keeping: f32,
+    /// Sum of the per tick steadiness of the controls
+    pub smoothness: f32,
+    pub ticks: u32,
+    pub last_controls: (f32, f32),
+}
+#[derive(Component, Default, Debug)]
+pub struct FitnessScore(pub f32);
 #[derive(Component)]
 pub struct CarsArray;
 #[derive(Component)]
 pub struct TrafficArray;
 #[derive(Component)]
 pub struct CameraFollowMarker;
+/// Marks a crashed controllable car along with what it crashed into
 #[derive(Component)]
-pub struct CarCollided;
+pub struct CarCollided(pub CrashCause);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CrashCause {
+    Traffic,
+    /// One of the lines on each side of the road
+    RoadEdge,
+}
 
 /* Road components    */
 #[derive(Component)]
@@ -34,9 +66,88 @@ pub struct RoadLine;
 /// Marker struct for the road background
 #[derive(Component)]
 pub struct Pavement;
+/// Piece of the road background or markings, laid `offset` to the right of the centreline
+/// at `arc_length` and moved along the road with the camera
+#[derive(Component)]
+pub struct RoadPiece {
+    pub arc_length: f32,
+    pub offset: f32,
+}
 
 /* UI components      */
 #[derive(Component)]
 pub struct SaveButton;
 #[derive(Component)]
 pub struct LoadButton;
+/// Overlay showing live statistics of the simulation
+#[derive(Component)]
+pub struct HudText;
+/// Chart of the fitness history, `generations` being the count currently drawn
+#[derive(Component, Default)]
+pub struct HistoryChart {
+    pub generations: usize,
+}
+/// Writes the fitness history as CSV
+#[derive(Component)]
+pub struct ExportHistoryButton;
+/// Car driven with the arrow keys instead of a network
+#[derive(Component)]
+pub struct HumanDriver;
+/// Cycles through the [`crate::resources::ManualDriving`] modes
+#[derive(Component)]
+pub struct ManualDrivingButton;
+/// Controls the speed of the simulation
+#[derive(Component)]
+pub struct TimeControlButton(pub TimeAction);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeAction {
+    TogglePause,
+    /// Pauses and runs a single fixed step
+    Step,
+    Slower,
+    Faster,
+}
+/// Text of the pause button, telling whether it pauses or resumes
+#[derive(Component)]
+pub struct PauseLabel;
+/// Column where the notifications are stacked
+#[derive(Component)]
+pub struct ToastArea;
+/// Notification removed once its timer finishes
+#[derive(Component)]
+pub struct Toast(pub Timer);
+/// Cycles the brain picked by the [`LoadButton`] through the library
+#[derive(Component)]
+pub struct BrowseButton;
+/// Switches the encoding used by the [`SaveButton`]
+#[derive(Component)]
+pub struct EncodingButton;
+/// Panel drawing the network of the followed car, `shape` holds the neuron count of every
+/// layer currently drawn
+#[derive(Component, Default)]
+pub struct NetworkPanel {
+    pub shape: Vec<usize>,
+}
+/// Neuron drawn in the [`NetworkPanel`], layer 0 being the inputs
+#[derive(Component)]
+pub struct NetworkNode {
+    pub layer: usize,
+    pub index: usize,
+}
+/// Weight drawn in the [`NetworkPanel`] between two neurons of the `level`
+#[derive(Component)]
+pub struct NetworkEdge {
+    pub level: usize,
+    pub from: usize,
+    pub to: usize,
+}
+
+/* Replay components  */
+/// Car of a replay, identified by its index
+#[derive(Component)]
+pub struct ReplayCar(pub usize);
+/// Traffic car of a replay, identified by its id
+#[derive(Component)]
+pub struct ReplayTraffic(pub u32);
+#[derive(Component)]
+pub struct PlaybackText;
@@ -1,6 +1,12 @@
 use crate::utils::lerp;
 use bevy::prelude::{Component, Entity, Reflect};
+use rand::seq::SliceRandom;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Outputs of a network: accelerate, turn left, turn right and brake
+pub const CONTROL_COUNT: usize = 4;
 
 #[derive(Component, Reflect, Debug)]
 pub struct NeuralNetwork {
@@ -9,15 +15,28 @@ pub struct NeuralNetwork {
 }
 
 impl NeuralNetwork {
-    pub fn new(neuron_count_per_level: &Vec<u8>, input_rays: Vec<Entity>) -> Self {
+    /// `activations` holds the activation of the hidden levels and of the output level
+    pub fn new(
+        neuron_count_per_level: &[u8],
+        input_rays: Vec<Entity>,
+        activations: (Activation, Activation),
+        rng: &mut impl Rng,
+    ) -> Self {
         let mut network = Self {
             levels: Vec::new(),
             input_rays,
         };
+        let output_level = neuron_count_per_level.len() - 2;
         (0..neuron_count_per_level.len() - 1).for_each(|i| {
             network.levels.push(NetworkLevel::new(
                 neuron_count_per_level[i],
                 neuron_count_per_level[i + 1],
+                if i == output_level {
+                    activations.1
+                } else {
+                    activations.0
+                },
+                rng,
             ));
         });
         network
@@ -27,13 +46,14 @@ impl NeuralNetwork {
         levels: Vec<NetworkLevel>,
         input_rays: Vec<Entity>,
         mutate_factor: f32,
+        rng: &mut impl Rng,
     ) -> Self {
         let mut network = Self { levels, input_rays };
-        network.mutate(mutate_factor);
+        network.mutate(mutate_factor, rng);
         network
     }
 
-    pub fn feed_forward<'a>(&'a mut self, inputs: &'a Vec<f32>) -> &Vec<f32> {
+    pub fn feed_forward<'a>(&'a mut self, inputs: &'a Vec<f32>) -> &'a Vec<f32> {
         let mut outputs = inputs;
         self.levels.iter_mut().for_each(|level| {
             outputs = level.feed_forward(outputs);
@@ -42,13 +62,151 @@ impl NeuralNetwork {
         outputs
     }
 
-    fn mutate(&mut self, amount: f32) {
+    /// Checks that the levels form a network `feed_forward` can run: every level is
+    /// consistent with itself, feeds the next one, and only holds finite values. Layers
+    /// hold between 1 and 255 neurons, as many as a [`NetworkConfig`] can describe, and the
+    /// last one drives the [`CONTROL_COUNT`] controls of the car.
+    ///
+    /// [`NetworkConfig`]: crate::resources::NetworkConfig
+    pub fn validate(levels: &[NetworkLevel]) -> Result<(), NetworkError> {
+        if levels.is_empty() {
+            return Err(NetworkError::NoLevels);
+        }
+        for (index, level) in levels.iter().enumerate() {
+            let (inputs, outputs) = (level.inputs.len(), level.outputs.len());
+            if level.weights.len() != inputs {
+                return Err(NetworkError::WeightRows {
+                    level: index,
+                    expected: inputs,
+                    found: level.weights.len(),
+                });
+            }
+            if let Some(row) = level.weights.iter().position(|row| row.len() != outputs) {
+                return Err(NetworkError::WeightColumns {
+                    level: index,
+                    row,
+                    expected: outputs,
+                    found: level.weights[row].len(),
+                });
+            }
+            if level.biases.len() != outputs {
+                return Err(NetworkError::Biases {
+                    level: index,
+                    expected: outputs,
+                    found: level.biases.len(),
+                });
+            }
+            if !level
+                .weights
+                .iter()
+                .flatten()
+                .chain(&level.biases)
+                .all(|v| v.is_finite())
+            {
+                return Err(NetworkError::NotFinite { level: index });
+            }
+        }
+        if let Some(level) = levels
+            .windows(2)
+            .position(|pair| pair[0].outputs.len() != pair[1].inputs.len())
+        {
+            return Err(NetworkError::Chain { level });
+        }
+        let layers = levels
+            .iter()
+            .map(|level| level.inputs.len())
+            .chain(levels.last().map(|level| level.outputs.len()));
+        for (layer, neurons) in layers.enumerate() {
+            if neurons == 0 || neurons > usize::from(u8::MAX) {
+                return Err(NetworkError::LayerSize { layer, neurons });
+            }
+        }
+        let outputs = levels[levels.len() - 1].outputs.len();
+        if outputs != CONTROL_COUNT {
+            return Err(NetworkError::Outputs(outputs));
+        }
+        Ok(())
+    }
+
+    /// Breeds a child from two or more parents with identical topologies
+    pub fn crossover(
+        parents: &[&[NetworkLevel]],
+        method: Crossover,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<NetworkLevel>, CrossoverError> {
+        let Some(first_parent) = parents.first() else {
+            return Err(CrossoverError::NoParents);
+        };
+        if let Some(parent) = parents
+            .iter()
+            .position(|parent| !NetworkLevel::same_topology(first_parent, parent))
+        {
+            return Err(CrossoverError::TopologyMismatch { parent });
+        }
+
+        let mut child = first_parent.to_vec();
+        for (level_idx, level) in child.iter_mut().enumerate() {
+            let parent_levels: Vec<&NetworkLevel> =
+                parents.iter().map(|parent| &parent[level_idx]).collect();
+            match method {
+                Crossover::Uniform => {
+                    for (o, bias) in level.biases.iter_mut().enumerate() {
+                        *bias = parent_levels.choose(rng).unwrap().biases[o];
+                    }
+                    for (i, weights) in level.weights.iter_mut().enumerate() {
+                        for (o, weight) in weights.iter_mut().enumerate() {
+                            *weight = parent_levels.choose(rng).unwrap().weights[i][o];
+                        }
+                    }
+                }
+                Crossover::PerNeuron => {
+                    for o in 0..level.biases.len() {
+                        level.copy_neuron(parent_levels.choose(rng).unwrap(), o);
+                    }
+                }
+                Crossover::SinglePoint => {
+                    let mut pair = parent_levels.choose_multiple(rng, 2);
+                    let head = pair.next().unwrap();
+                    let tail = pair.next().unwrap_or(head);
+                    let point = rng.gen_range(0..=level.biases.len());
+                    for o in 0..level.biases.len() {
+                        level.copy_neuron(if o < point { head } else { tail }, o);
+                    }
+                }
+                Crossover::Blend => {
+                    let shares: Vec<f32> = (0..parent_levels.len())
+                        .map(|_| rng.gen_range(0.0..1.) + f32::EPSILON)
+                        .collect();
+                    let total: f32 = shares.iter().sum();
+                    for (o, bias) in level.biases.iter_mut().enumerate() {
+                        *bias = parent_levels
+                            .iter()
+                            .zip(&shares)
+                            .map(|(parent, share)| parent.biases[o] * share / total)
+                            .sum();
+                    }
+                    for (i, weights) in level.weights.iter_mut().enumerate() {
+                        for (o, weight) in weights.iter_mut().enumerate() {
+                            *weight = parent_levels
+                                .iter()
+                                .zip(&shares)
+                                .map(|(parent, share)| parent.weights[i][o] * share / total)
+                                .sum();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(child)
+    }
+
+    fn mutate(&mut self, amount: f32, rng: &mut impl Rng) {
         self.levels.iter_mut().for_each(|level| {
             let _ = level
                 .biases
                 .iter_mut()
                 .map(|bias| {
-                    let random: f32 = rand::thread_rng().gen_range(-1.0..1.);
+                    let random: f32 = rng.gen_range(-1.0..1.);
                     *bias = lerp::<f32, f32>(*bias, random, amount);
                     *bias
                 })
@@ -58,7 +216,7 @@ impl NeuralNetwork {
                 *weight_vec = weight_vec
                     .iter_mut()
                     .map(|weight| {
-                        let random: f32 = rand::thread_rng().gen_range(-1.0..1.);
+                        let random: f32 = rng.gen_range(-1.0..1.);
                         *weight = lerp::<f32, f32>(*weight, random, amount);
                         *weight
                     })
@@ -74,35 +232,69 @@ pub struct NetworkLevel {
     pub weights: Vec<Vec<f32>>,
     pub outputs: Vec<f32>,
     pub biases: Vec<f32>,
+    /// Brains saved before activations were configurable default to a step function
+    #[reflect(default)]
+    pub activation: Activation,
 }
 
 impl NetworkLevel {
-    pub fn new(input_count: u8, output_count: u8) -> Self {
+    pub fn new(
+        input_count: u8,
+        output_count: u8,
+        activation: Activation,
+        rng: &mut impl Rng,
+    ) -> Self {
         let mut level = NetworkLevel {
             inputs: vec![0.; input_count.into()],
             weights: vec![vec![]; input_count.into()],
             outputs: vec![0.; output_count.into()],
             biases: vec![0.; output_count.into()],
+            activation,
         };
         (0..level.weights.len()).for_each(|i| level.weights[i] = vec![0.; output_count.into()]);
 
-        level.randomize();
+        level.randomize(rng);
         level
     }
 
-    fn randomize(&mut self) {
+    /// Checks that both networks have the same amount of levels with matching dimensions and activations
+    pub fn same_topology(a: &[NetworkLevel], b: &[NetworkLevel]) -> bool {
+        a.len() == b.len()
+            && a.iter().zip(b).all(|(a, b)| {
+                a.activation == b.activation
+                    && a.inputs.len() == b.inputs.len()
+                    && a.outputs.len() == b.outputs.len()
+                    && a.biases.len() == b.biases.len()
+                    && a.weights.len() == b.weights.len()
+                    && a.weights
+                        .iter()
+                        .zip(&b.weights)
+                        .all(|(a, b)| a.len() == b.len())
+            })
+    }
+
+    /// Takes the incoming weights and the bias of the `output` neuron from `other`
+    fn copy_neuron(&mut self, other: &NetworkLevel, output: usize) {
+        self.biases[output] = other.biases[output];
+        for (weights, other_weights) in self.weights.iter_mut().zip(&other.weights) {
+            weights[output] = other_weights[output];
+        }
+    }
+
+    fn randomize(&mut self, rng: &mut impl Rng) {
         (0..self.inputs.len()).for_each(|i| {
             (0..self.outputs.len()).for_each(|o| {
-                self.weights[i][o] = rand::thread_rng().gen_range(-1.0..1.);
+                self.weights[i][o] = rng.gen_range(-1.0..1.);
             });
         });
 
         (0..self.biases.len()).for_each(|i| {
-            self.biases[i] = rand::thread_rng().gen_range(-1.0..1.);
+            self.biases[i] = rng.gen_range(-1.0..1.);
         });
     }
 
-    fn feed_forward(&mut self, inputs: &[f32]) -> &Vec<f32> {
+    /// Keeps the inputs and the outputs of the level, which training relies on
+    pub(crate) fn feed_forward(&mut self, inputs: &[f32]) -> &Vec<f32> {
         (0..self.inputs.len()).for_each(|i| {
             self.inputs[i] = inputs[i];
         });
@@ -110,13 +302,572 @@ impl NetworkLevel {
         (0..self.outputs.len()).for_each(|oi| {
             let mut sum = 0.;
             (0..self.inputs.len()).for_each(|i| sum += self.inputs[i] * self.weights[i][oi]);
-            if sum > self.biases[oi] {
-                self.outputs[oi] = 1.;
-            } else {
-                self.outputs[oi] = 0.;
-            }
+            self.outputs[oi] = sum - self.biases[oi];
         });
+        self.activation.apply(&mut self.outputs);
 
         &self.outputs
     }
 }
+
+/// Function applied to the weighted sums of a level to produce its outputs
+#[derive(Reflect, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum Activation {
+    /// Outputs 1 when the sum exceeds the bias, 0 otherwise
+    #[default]
+    Step,
+    Sigmoid,
+    Tanh,
+    Relu,
+    LeakyRelu,
+    Linear,
+    /// Normalizes the outputs into probabilities, meant for the output level
+    Softmax,
+}
+
+impl Activation {
+    /// Replaces every weighted sum, already offset by its bias, with its activation
+    pub fn apply(self, sums: &mut [f32]) {
+        match self {
+            Activation::Softmax => {
+                let max = sums.iter().copied().fold(f32::MIN, f32::max);
+                sums.iter_mut().for_each(|x| *x = (*x - max).exp());
+                let total: f32 = sums.iter().sum();
+                sums.iter_mut().for_each(|x| *x /= total);
+            }
+            activation => sums.iter_mut().for_each(|x| *x = activation.activate(*x)),
+        }
+    }
+
+    /// Whether the activation has a gradient to train with, the step function being flat
+    pub fn is_differentiable(self) -> bool {
+        self != Activation::Step
+    }
+
+    /// Turns the gradients of a loss with respect to the `outputs` of the activation into
+    /// gradients with respect to the weighted sums it was applied to
+    pub fn backpropagate(self, outputs: &[f32], gradients: &mut [f32]) {
+        match self {
+            Activation::Softmax => {
+                let dot: f32 = outputs.iter().zip(&*gradients).map(|(y, g)| y * g).sum();
+                gradients
+                    .iter_mut()
+                    .zip(outputs)
+                    .for_each(|(g, y)| *g = y * (*g - dot));
+            }
+            activation => gradients
+                .iter_mut()
+                .zip(outputs)
+                .for_each(|(g, y)| *g *= activation.derivative(*y)),
+        }
+    }
+
+    /// Derivative of the activation, expressed with its output `y`
+    fn derivative(self, y: f32) -> f32 {
+        match self {
+            Activation::Step => 0.,
+            Activation::Sigmoid => y * (1. - y),
+            Activation::Tanh => 1. - y * y,
+            Activation::Relu => {
+                if y > 0. {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Activation::LeakyRelu => {
+                if y > 0. {
+                    1.
+                } else {
+                    0.01
+                }
+            }
+            Activation::Linear | Activation::Softmax => 1.,
+        }
+    }
+
+    fn activate(self, x: f32) -> f32 {
+        match self {
+            Activation::Step => {
+                if x > 0. {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Activation::Sigmoid => 1. / (1. + (-x).exp()),
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.max(0.),
+            Activation::LeakyRelu => {
+                if x > 0. {
+                    x
+                } else {
+                    0.01 * x
+                }
+            }
+            Activation::Linear | Activation::Softmax => x,
+        }
+    }
+}
+
+/// Ways of combining the levels of several parent networks into a child network
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Crossover {
+    /// Every weight and bias comes from a random parent
+    Uniform,
+    /// Every neuron takes its incoming weights and bias from a random parent
+    PerNeuron,
+    /// Every level takes the neurons before a random point from one parent and the rest from another
+    SinglePoint,
+    /// Every weight and bias is a random weighted average of the parents' values
+    Blend,
+}
+
+#[derive(Debug)]
+pub enum CrossoverError {
+    NoParents,
+    /// The parent at this index has a different topology than the first parent
+    TopologyMismatch {
+        parent: usize,
+    },
+}
+
+impl fmt::Display for CrossoverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CrossoverError::NoParents => write!(f, "crossover requires at least one parent"),
+            CrossoverError::TopologyMismatch { parent } => write!(
+                f,
+                "parent {parent} does not have the same topology as the first parent"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CrossoverError {}
+
+/// Ways a network can be malformed, `level` being the index of the offending level
+#[derive(Debug, PartialEq)]
+pub enum NetworkError {
+    NoLevels,
+    /// A weight row is expected for every input
+    WeightRows {
+        level: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// Every weight row is expected to have a weight for every output
+    WeightColumns {
+        level: usize,
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A bias is expected for every output
+    Biases {
+        level: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// The outputs of the level don't match the inputs of the next one
+    Chain {
+        level: usize,
+    },
+    /// A weight or a bias is infinite or NaN
+    NotFinite {
+        level: usize,
+    },
+    /// The layer, counted from the inputs, is empty or too large to be configured
+    LayerSize {
+        layer: usize,
+        neurons: usize,
+    },
+    /// The output layer doesn't have a neuron for each control of the car
+    Outputs(usize),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::NoLevels => write!(f, "the network has no levels"),
+            NetworkError::WeightRows {
+                level,
+                expected,
+                found,
+            } => write!(
+                f,
+                "level {level} has {found} weight rows for {expected} inputs"
+            ),
+            NetworkError::WeightColumns {
+                level,
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {row} of level {level} has {found} weights for {expected} outputs"
+            ),
+            NetworkError::Biases {
+                level,
+                expected,
+                found,
+            } => write!(f, "level {level} has {found} biases for {expected} outputs"),
+            NetworkError::Chain { level } => write!(
+                f,
+                "the outputs of level {level} don't match the inputs of level {}",
+                level + 1
+            ),
+            NetworkError::NotFinite { level } => {
+                write!(f, "level {level} holds infinite or NaN values")
+            }
+            NetworkError::LayerSize { layer, neurons } => write!(
+                f,
+                "layer {layer} has {neurons} neurons, between 1 and {} are supported",
+                u8::MAX
+            ),
+            NetworkError::Outputs(outputs) => write!(
+                f,
+                "the network has {outputs} outputs, {CONTROL_COUNT} are expected, one per \
+                 control of the car"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brain::{read_brain, write_brain, Brain, BrainEncoding, BrainMetadata};
+    use crate::resources::NetworkConfig;
+    use crate::utils::TempDir;
+    use crate::PersistenceError;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    fn level(inputs: usize, outputs: usize) -> NetworkLevel {
+        NetworkLevel {
+            inputs: vec![0.; inputs],
+            weights: vec![vec![0.5; outputs]; inputs],
+            outputs: vec![0.; outputs],
+            biases: vec![0.25; outputs],
+            activation: Activation::Tanh,
+        }
+    }
+
+    fn network() -> Vec<NetworkLevel> {
+        vec![level(3, 5), level(5, 4)]
+    }
+
+    #[test]
+    fn accepts_working_networks() {
+        assert_eq!(NeuralNetwork::validate(&network()), Ok(()));
+        assert_eq!(NeuralNetwork::validate(&[level(255, 4)]), Ok(()));
+    }
+
+    #[test]
+    fn rejects_broken_levels() {
+        let broken = |change: fn(&mut Vec<NetworkLevel>)| {
+            let mut levels = network();
+            change(&mut levels);
+            NeuralNetwork::validate(&levels)
+        };
+
+        assert_eq!(broken(|l| l.clear()), Err(NetworkError::NoLevels));
+        assert_eq!(
+            broken(|l| {
+                l[1].weights.pop();
+            }),
+            Err(NetworkError::WeightRows {
+                level: 1,
+                expected: 5,
+                found: 4
+            })
+        );
+        assert_eq!(
+            broken(|l| {
+                l[0].weights[2].push(1.);
+            }),
+            Err(NetworkError::WeightColumns {
+                level: 0,
+                row: 2,
+                expected: 5,
+                found: 6
+            })
+        );
+        assert_eq!(
+            broken(|l| {
+                l[1].biases.pop();
+            }),
+            Err(NetworkError::Biases {
+                level: 1,
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(
+            broken(|l| l[1] = level(6, 4)),
+            Err(NetworkError::Chain { level: 0 })
+        );
+        assert_eq!(
+            broken(|l| l[0].weights[1][3] = f32::NAN),
+            Err(NetworkError::NotFinite { level: 0 })
+        );
+        assert_eq!(
+            broken(|l| l[1].biases[0] = f32::INFINITY),
+            Err(NetworkError::NotFinite { level: 1 })
+        );
+    }
+
+    #[test]
+    fn rejects_layers_a_config_cannot_describe() {
+        assert_eq!(
+            NeuralNetwork::validate(&[level(0, 4)]),
+            Err(NetworkError::LayerSize {
+                layer: 0,
+                neurons: 0
+            })
+        );
+        assert_eq!(
+            NeuralNetwork::validate(&[level(3, 0), level(0, 4)]),
+            Err(NetworkError::LayerSize {
+                layer: 1,
+                neurons: 0
+            })
+        );
+        assert_eq!(
+            NeuralNetwork::validate(&[level(3, 300), level(300, 4)]),
+            Err(NetworkError::LayerSize {
+                layer: 1,
+                neurons: 300
+            })
+        );
+        assert_eq!(
+            NeuralNetwork::validate(&[level(256, 4)]),
+            Err(NetworkError::LayerSize {
+                layer: 0,
+                neurons: 256
+            })
+        );
+        assert_eq!(
+            NeuralNetwork::validate(&[level(3, 5), level(5, 3)]),
+            Err(NetworkError::Outputs(3))
+        );
+    }
+
+    #[test]
+    fn refuses_to_load_broken_brains() {
+        let dir = TempDir::new("network");
+        let broken_networks = [
+            (
+                vec![level(3, 5), level(6, 4)],
+                NetworkError::Chain { level: 0 },
+            ),
+            (
+                vec![level(3, 300), level(300, 4)],
+                NetworkError::LayerSize {
+                    layer: 1,
+                    neurons: 300,
+                },
+            ),
+            (vec![level(3, 5), level(5, 2)], NetworkError::Outputs(2)),
+            (
+                {
+                    let mut levels = network();
+                    levels[1].weights[0][0] = f32::NAN;
+                    levels
+                },
+                NetworkError::NotFinite { level: 1 },
+            ),
+        ];
+        for (levels, expected) in broken_networks {
+            let brain = Brain {
+                metadata: BrainMetadata::now(0, 0., 0., 1, NetworkConfig::default()),
+                levels,
+            };
+            let path = write_brain(&dir, &brain, BrainEncoding::Ron).unwrap();
+            match read_brain(&path) {
+                Err(PersistenceError::InvalidNetwork(error)) => assert_eq!(error, expected),
+                other => panic!("{}: {other:?}", path.display()),
+            }
+        }
+    }
+
+    fn parent(seed: u64) -> Vec<NetworkLevel> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        NeuralNetwork::new(
+            &[5, 6, 4],
+            Vec::new(),
+            (Activation::Tanh, Activation::Sigmoid),
+            &mut rng,
+        )
+        .levels
+    }
+
+    fn parents() -> Vec<Vec<NetworkLevel>> {
+        (1..=3).map(parent).collect()
+    }
+
+    fn breed(parents: &[Vec<NetworkLevel>], method: Crossover, seed: u64) -> Vec<NetworkLevel> {
+        let parents: Vec<&[NetworkLevel]> = parents.iter().map(Vec::as_slice).collect();
+        NeuralNetwork::crossover(&parents, method, &mut StdRng::seed_from_u64(seed)).unwrap()
+    }
+
+    /// Bias and incoming weights of the `output` neuron
+    fn neuron(level: &NetworkLevel, output: usize) -> Vec<f32> {
+        let weights = level.weights.iter().map(|weights| weights[output]);
+        weights.chain([level.biases[output]]).collect()
+    }
+
+    /// Index of the parent the neuron was taken from as a whole
+    fn neuron_source(
+        parents: &[Vec<NetworkLevel>],
+        child: &NetworkLevel,
+        l: usize,
+        o: usize,
+    ) -> usize {
+        parents
+            .iter()
+            .position(|parent| neuron(&parent[l], o) == neuron(child, o))
+            .unwrap_or_else(|| panic!("neuron {o} of level {l} mixes its parents"))
+    }
+
+    #[test]
+    fn rejects_parents_that_cannot_breed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(matches!(
+            NeuralNetwork::crossover(&[], Crossover::Uniform, &mut rng),
+            Err(CrossoverError::NoParents)
+        ));
+
+        let mut activation = parent(2);
+        activation[1].activation = Activation::Relu;
+        let mut rng2 = StdRng::seed_from_u64(1);
+        let wider = NeuralNetwork::new(
+            &[5, 7, 4],
+            Vec::new(),
+            (Activation::Tanh, Activation::Sigmoid),
+            &mut rng2,
+        )
+        .levels;
+        let deeper = NeuralNetwork::new(
+            &[5, 6, 6, 4],
+            Vec::new(),
+            (Activation::Tanh, Activation::Sigmoid),
+            &mut rng2,
+        )
+        .levels;
+        let mut ragged = parent(2);
+        ragged[0].weights[3].pop();
+        let first = parent(1);
+        for (index, other) in [activation, wider, deeper, ragged].iter().enumerate() {
+            assert!(!NetworkLevel::same_topology(&first, other), "case {index}");
+            let parents: [&[NetworkLevel]; 3] = [&first, &first, other];
+            for method in [Crossover::Uniform, Crossover::Blend] {
+                assert!(matches!(
+                    NeuralNetwork::crossover(&parents, method, &mut rng),
+                    Err(CrossoverError::TopologyMismatch { parent: 2 })
+                ));
+            }
+        }
+        assert!(NetworkLevel::same_topology(&first, &parent(2)));
+    }
+
+    #[test]
+    fn uniform_children_take_every_value_from_a_parent() {
+        let parents = parents();
+        let mut sources = HashSet::new();
+        for seed in 0..5 {
+            let child = breed(&parents, Crossover::Uniform, seed);
+            assert!(NetworkLevel::same_topology(&child, &parents[0]));
+            for (l, level) in child.iter().enumerate() {
+                let values = level.weights.iter().flatten().chain(&level.biases);
+                for (position, value) in values.enumerate() {
+                    let mut parent_values = parents.iter().map(|parent| {
+                        let level = &parent[l];
+                        *level
+                            .weights
+                            .iter()
+                            .flatten()
+                            .chain(&level.biases)
+                            .nth(position)
+                            .unwrap()
+                    });
+                    let source = parent_values.position(|v| v == *value);
+                    sources.insert(source.expect("a value comes from no parent"));
+                }
+            }
+        }
+        assert_eq!(sources.len(), 3, "every parent should pass some values on");
+    }
+
+    #[test]
+    fn per_neuron_children_take_whole_neurons_from_a_parent() {
+        let parents = parents();
+        let mut sources = HashSet::new();
+        for seed in 0..5 {
+            let child = breed(&parents, Crossover::PerNeuron, seed);
+            for (l, level) in child.iter().enumerate() {
+                for o in 0..level.biases.len() {
+                    sources.insert(neuron_source(&parents, level, l, o));
+                }
+            }
+        }
+        assert_eq!(sources.len(), 3, "every parent should pass some neurons on");
+    }
+
+    #[test]
+    fn single_point_children_split_every_level_between_two_parents() {
+        let parents = parents();
+        for seed in 0..10 {
+            let child = breed(&parents, Crossover::SinglePoint, seed);
+            for (l, level) in child.iter().enumerate() {
+                let sources: Vec<usize> = (0..level.biases.len())
+                    .map(|o| neuron_source(&parents, level, l, o))
+                    .collect();
+                // The neurons before the point come from one parent, the rest from another
+                let changes = sources.windows(2).filter(|pair| pair[0] != pair[1]).count();
+                assert!(changes <= 1, "level {l} of seed {seed}: {sources:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn blend_children_stay_between_their_parents() {
+        let parents = parents();
+        for seed in 0..5 {
+            let child = breed(&parents, Crossover::Blend, seed);
+            for (l, level) in child.iter().enumerate() {
+                let values = level.weights.iter().flatten().chain(&level.biases);
+                for (position, value) in values.enumerate() {
+                    let (min, max) = parents
+                        .iter()
+                        .map(|parent| {
+                            let level = &parent[l];
+                            let mut values = level.weights.iter().flatten().chain(&level.biases);
+                            *values.nth(position).unwrap()
+                        })
+                        .fold((f32::MAX, f32::MIN), |(min, max), v| {
+                            (min.min(v), max.max(v))
+                        });
+                    assert!(
+                        (min - 1e-6..=max + 1e-6).contains(value),
+                        "{value} outside of {min}..{max}"
+                    );
+                }
+            }
+        }
+        // A single parent breeds itself
+        let only = [parents[0].clone()];
+        let child = breed(&only, Crossover::Blend, 0);
+        for (a, b) in child.iter().zip(&only[0]) {
+            assert!(a
+                .biases
+                .iter()
+                .zip(&b.biases)
+                .all(|(a, b)| (a - b).abs() < 1e-6));
+        }
+    }
+}
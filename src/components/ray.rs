@@ -1,3 +1,4 @@
+use crate::utils::rect_corners;
 use bevy::prelude::{
     Bundle, Color, Component, Entity, Quat, Sprite, SpriteBundle, Transform, Vec2, Vec3,
 };
@@ -12,47 +13,28 @@ pub struct Ray {
 }
 
 impl Ray {
+    /// Value fed to the networks: -1 when nothing is hit, rising to 1 as the closest
+    /// collision gets nearer
+    pub fn input(&self) -> f32 {
+        self.collisions
+            .iter()
+            .map(|collision| collision.1)
+            .min_by(f32::total_cmp)
+            .map_or(-1., |closest| 1. - closest)
+    }
+
     pub fn get_intersecting_point(
         &self,
         car_xform: &Transform,
-        target_pos: &Vec3,
+        target_xform: &Transform,
         target_size: Vec2,
     ) -> Option<(Vec2, f32)> {
         let rs = car_xform.translation;
         let re = car_xform.translation
             + ((Quat::from_rotation_z(self.angle) * car_xform.rotation) * Vec3::Y) * self.length;
-        let t_min = target_pos.truncate() - target_size / 2.0;
-        let t_max = target_pos.truncate() + target_size / 2.0;
-        let target_segments: [(Vec2, Vec2); 4] = [
-            (
-                t_min,
-                Vec2 {
-                    x: t_min.x,
-                    y: t_max.y,
-                },
-            ),
-            (
-                Vec2 {
-                    x: t_min.x,
-                    y: t_max.y,
-                },
-                t_max,
-            ),
-            (
-                t_max,
-                Vec2 {
-                    x: t_max.x,
-                    y: t_min.y,
-                },
-            ),
-            (
-                Vec2 {
-                    x: t_max.x,
-                    y: t_min.y,
-                },
-                t_min,
-            ),
-        ];
+        let corners = rect_corners(target_xform, target_size);
+        let target_segments: [(Vec2, Vec2); 4] =
+            [0, 1, 2, 3].map(|i| (corners[i], corners[(i + 1) % 4]));
 
         let mut colliding_points: Vec<(Vec2, f32)> = vec![];
 
@@ -0,0 +1,103 @@
+use crate::components::CONTROL_COUNT;
+use crate::resources::{Config, NetworkConfig, RoadProperties};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/// Contents of a RON configuration file, missing values keep their defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub simulation: Config,
+    pub network: NetworkConfig,
+    /// Lanes and width of the road, as wide as the window when missing
+    pub road: Option<RoadProperties>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    /// A value that parsed but can't be simulated
+    Invalid {
+        field: &'static str,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "can't read the configuration: {e}"),
+            ConfigError::Parse(e) => write!(f, "invalid configuration at {e}"),
+            ConfigError::Invalid { field, reason } => write!(f, "`{field}` {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl ConfigFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+        let file: Self = ron::from_str(&contents).map_err(ConfigError::Parse)?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    /// Checks the settings can be simulated, whether they come from a file or not
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, reason| Err(ConfigError::Invalid { field, reason });
+        let simulation = &self.simulation;
+        let network = &self.network;
+
+        if simulation.controlllable_cars == 0 {
+            return invalid("simulation.controllable_cars", "must be at least 1");
+        }
+        if simulation.survivors == 0 || simulation.survivors > simulation.controlllable_cars {
+            return invalid(
+                "simulation.survivors",
+                "must be between 1 and the number of controllable cars",
+            );
+        }
+        if simulation.max_generation_duration <= 0. {
+            return invalid("simulation.max_generation_duration", "must be positive");
+        }
+        if simulation.stagnation_timeout <= 0. {
+            return invalid("simulation.stagnation_timeout", "must be positive");
+        }
+        if !(0.0..=1.0).contains(&simulation.road_curviness) {
+            return invalid("simulation.road_curviness", "must be between 0 and 1");
+        }
+        if simulation.fitness.is_empty() {
+            return invalid("simulation.fitness", "needs at least one fitness function");
+        }
+        if network.input_neuron_count == 0 {
+            return invalid("network.input_neuron_count", "must be at least 1");
+        }
+        if network.hidden_layers > 1 && network.hidden_layers_neuron_count == 0 {
+            return invalid("network.hidden_layers_neuron_count", "must be at least 1");
+        }
+        if usize::from(network.output_neuron_count) != CONTROL_COUNT {
+            return invalid(
+                "network.output_neuron_count",
+                "must be 4, one per control of the car",
+            );
+        }
+        if network.input_ray_length <= 0. {
+            return invalid("network.input_ray_length", "must be positive");
+        }
+        if !(0.0..=1.0).contains(&network.mutate_factor) {
+            return invalid("network.mutate_factor", "must be between 0 and 1");
+        }
+        if let Some(road) = self.road {
+            if road.lane_count == 0 {
+                return invalid("road.lane_count", "must be at least 1");
+            }
+            if road.width <= 0. {
+                return invalid("road.width", "must be positive");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! Demonstrations recorded while a human drives, one CSV file per session. The file starts
+//! with a `#` comment describing the sensors, followed by one row per fixed tick:
+//!
+//! ```text
+//! # ray_length: 130, ray_spread: 2.827433
+//! speed,ray_0,ray_1,...,acceleration,turn_direction
+//! 42.5,-1,0.37,...,1,0
+//! ```
+//!
+//! Rays are the inputs the networks get, -1 when nothing is hit up to 1 when touching,
+//! ordered from the leftmost ray. `acceleration` and `turn_direction` are the controls
+//! chosen by the driver, a positive turn being to the left.
+
+use crate::brain::utc_date;
+use crate::resources::NetworkConfig;
+use crate::utils::create_new_file;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Folder holding every recorded session
+pub const DATASETS_DIR: &str = "assets/demonstrations";
+
+/// What the car sensed on a tick and how it was driven
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub speed: f32,
+    pub rays: Vec<f32>,
+    pub acceleration: f32,
+    pub turn_direction: f32,
+}
+
+impl Sample {
+    /// Outputs a network should give to drive like the sample: accelerate, turn left, turn
+    /// right and brake, each 1 when pressed and 0 otherwise
+    pub fn targets(&self) -> [f32; 4] {
+        let pressed = |pressed: bool| if pressed { 1. } else { 0. };
+        [
+            pressed(self.acceleration > 0.),
+            pressed(self.turn_direction > 0.),
+            pressed(self.turn_direction < 0.),
+            pressed(self.acceleration < 0.),
+        ]
+    }
+}
+
+/// Samples of one or several sessions recorded with the same sensors
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub ray_length: f32,
+    pub ray_spread: f32,
+    pub samples: Vec<Sample>,
+}
+
+impl Dataset {
+    pub fn rays(&self) -> usize {
+        self.samples.first().map_or(0, |sample| sample.rays.len())
+    }
+}
+
+/// Reads a session file, or every session of a folder
+pub fn read_dataset(path: impl AsRef<Path>) -> Result<Dataset, DatasetError> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return read_session(path);
+    }
+
+    let files = std::fs::read_dir(path).map_err(|e| DatasetError::Io(path.to_path_buf(), e))?;
+    let mut sessions: Vec<PathBuf> = files
+        .filter_map(|file| file.ok().map(|file| file.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+        .collect();
+    sessions.sort();
+    let mut dataset: Option<Dataset> = None;
+    for session_path in sessions {
+        let session = read_session(&session_path)?;
+        match &mut dataset {
+            None => dataset = Some(session),
+            Some(dataset) => {
+                if (dataset.ray_length, dataset.ray_spread, dataset.rays())
+                    != (session.ray_length, session.ray_spread, session.rays())
+                {
+                    return Err(DatasetError::SensorMismatch(session_path));
+                }
+                dataset.samples.extend(session.samples);
+            }
+        }
+    }
+    dataset.ok_or_else(|| DatasetError::Empty(path.to_path_buf()))
+}
+
+fn read_session(path: &Path) -> Result<Dataset, DatasetError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| DatasetError::Io(path.to_path_buf(), e))?;
+    let parse_error = |line: usize, reason: &str| DatasetError::Parse {
+        path: path.to_path_buf(),
+        line: line + 1,
+        reason: reason.to_string(),
+    };
+    let mut lines = contents.lines().enumerate();
+
+    let (_, sensors) = lines.next().ok_or_else(|| parse_error(0, "empty file"))?;
+    let sensor = |name: &str| -> Option<f32> {
+        let prefix = format!("{name}: ");
+        let start = sensors.find(&prefix)? + prefix.len();
+        let value = sensors[start..].split(',').next()?;
+        value.trim().parse().ok()
+    };
+    let (Some(ray_length), Some(ray_spread)) = (sensor("ray_length"), sensor("ray_spread")) else {
+        return Err(parse_error(0, "missing the ray length or spread"));
+    };
+    let (_, header) = lines
+        .next()
+        .ok_or_else(|| parse_error(1, "missing header"))?;
+    let rays = header.split(',').count().saturating_sub(3);
+
+    let mut samples = Vec::new();
+    for (line, row) in lines.filter(|(_, row)| !row.trim().is_empty()) {
+        let values: Vec<f32> = row
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| parse_error(line, "not a number"))?;
+        if values.len() != rays + 3 {
+            return Err(parse_error(line, "wrong column count"));
+        }
+        samples.push(Sample {
+            speed: values[0],
+            rays: values[1..=rays].to_vec(),
+            acceleration: values[rays + 1],
+            turn_direction: values[rays + 2],
+        });
+    }
+    if samples.is_empty() {
+        return Err(DatasetError::Empty(path.to_path_buf()));
+    }
+    Ok(Dataset {
+        ray_length,
+        ray_spread,
+        samples,
+    })
+}
+
+/// Writes the samples of a session as they are recorded
+pub struct DatasetWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    rays: usize,
+    samples: usize,
+}
+
+impl DatasetWriter {
+    /// Starts a new session file in `dir`, named after the current date and never replacing
+    /// an earlier session started in the same second
+    pub fn create(dir: impl AsRef<Path>, sensors: &NetworkConfig) -> Result<Self, DatasetError> {
+        let dir = dir.as_ref();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let stem = utc_date(timestamp, "", "", "-");
+        let (path, file) = create_new_file(dir, &stem, "csv")
+            .map_err(|e| DatasetError::Io(dir.join(format!("{stem}.csv")), e))?;
+        let io_error = |e| DatasetError::Io(path.clone(), e);
+
+        let mut writer = BufWriter::new(file);
+        let rays = usize::from(sensors.input_neuron_count);
+        let ray_columns: Vec<String> = (0..rays).map(|i| format!("ray_{i}")).collect();
+        writeln!(
+            writer,
+            "# ray_length: {}, ray_spread: {}\nspeed,{},acceleration,turn_direction",
+            sensors.input_ray_length,
+            sensors.input_ray_spread,
+            ray_columns.join(",")
+        )
+        .map_err(io_error)?;
+        Ok(Self {
+            path,
+            writer,
+            rays,
+            samples: 0,
+        })
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> Result<(), DatasetError> {
+        if sample.rays.len() != self.rays {
+            return Err(DatasetError::RayCount {
+                expected: self.rays,
+                found: sample.rays.len(),
+            });
+        }
+        let rays: Vec<String> = sample.rays.iter().map(f32::to_string).collect();
+        writeln!(
+            self.writer,
+            "{},{},{},{}",
+            sample.speed,
+            rays.join(","),
+            sample.acceleration,
+            sample.turn_direction
+        )
+        .map_err(|e| DatasetError::Io(self.path.clone(), e))?;
+        self.samples += 1;
+        Ok(())
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Flushes the session, returning the path of its file
+    pub fn finish(mut self) -> Result<PathBuf, DatasetError> {
+        self.writer
+            .flush()
+            .map_err(|e| DatasetError::Io(self.path.clone(), e))?;
+        Ok(self.path)
+    }
+}
+
+/// Reasons a dataset couldn't be written or read
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+    /// A session of the folder was recorded with other sensors than the previous ones
+    SensorMismatch(PathBuf),
+    Empty(PathBuf),
+    /// A sample doesn't have as many rays as the session
+    RayCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            DatasetError::Parse { path, line, reason } => {
+                write!(f, "{}:{line}: {reason}", path.display())
+            }
+            DatasetError::SensorMismatch(path) => write!(
+                f,
+                "{}: recorded with other sensors than the previous sessions",
+                path.display()
+            ),
+            DatasetError::Empty(path) => write!(f, "{}: no sample recorded", path.display()),
+            DatasetError::RayCount { expected, found } => {
+                write!(f, "a sample has {found} rays instead of {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    #[test]
+    fn sessions_started_together_keep_their_own_file() {
+        let dir = TempDir::new("dataset");
+        let sensors = NetworkConfig::default();
+        let sample = |speed| Sample {
+            speed,
+            rays: vec![-1.; usize::from(sensors.input_neuron_count)],
+            acceleration: 1.,
+            turn_direction: 0.,
+        };
+
+        let mut first = DatasetWriter::create(&dir, &sensors).unwrap();
+        let mut second = DatasetWriter::create(&dir, &sensors).unwrap();
+        first.write(&sample(1.)).unwrap();
+        second.write(&sample(2.)).unwrap();
+        second.write(&sample(3.)).unwrap();
+        let (first, second) = (first.finish().unwrap(), second.finish().unwrap());
+
+        assert_ne!(first, second);
+        assert_eq!(read_dataset(&first).unwrap().samples.len(), 1);
+        assert_eq!(read_dataset(&second).unwrap().samples.len(), 2);
+        assert_eq!(read_dataset(&dir).unwrap().samples.len(), 3);
+    }
+}
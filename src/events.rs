@@ -1,7 +1,26 @@
+use crate::brain::Brain;
 use crate::components;
+use crate::resources::CrashCounts;
 use bevy::prelude::{Entity, Event};
 
 #[derive(Event)]
-pub struct LoadNetworkEvent(pub Vec<components::NetworkLevel>);
+pub struct LoadNetworkEvent(pub Brain);
 #[derive(Event)]
 pub struct ChangeTargetEvent(pub Entity, pub Option<Entity>);
+/// Networks of the selected survivors, best first, used to breed the next generation
+#[derive(Event)]
+pub struct NextGenerationEvent(pub Vec<Vec<components::NetworkLevel>>);
+/// Message shown to the user for a few seconds
+#[derive(Event)]
+pub struct NotificationEvent {
+    pub message: String,
+    pub is_error: bool,
+}
+/// Fitness of every car of the generation that just ended, best first
+#[derive(Event)]
+pub struct GenerationEndedEvent {
+    pub generation: u32,
+    pub fitness: Vec<f32>,
+    pub crashes: CrashCounts,
+    pub end_reason: &'static str,
+}
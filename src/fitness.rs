@@ -0,0 +1,114 @@
+use crate::components::CarStats;
+use serde::{Deserialize, Serialize};
+
+/// Scores how well a controllable car is performing, higher is better
+pub trait Fitness {
+    fn score(&self, stats: &CarStats) -> f32;
+}
+
+/// Built-in fitness functions that can be selected through the [`Config`](crate::resources::Config)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FitnessKind {
+    Distance,
+    /// Distance minus the given penalty for every second alive
+    DistanceTimePenalty(f32),
+    CarsOvertaken,
+    SurvivalTime,
+    LaneKeeping,
+    Smoothness,
+}
+
+impl FitnessKind {
+    pub fn build(self) -> Box<dyn Fitness + Send + Sync> {
+        match self {
+            FitnessKind::Distance => Box::new(Distance),
+            FitnessKind::DistanceTimePenalty(penalty) => Box::new(DistanceTimePenalty(penalty)),
+            FitnessKind::CarsOvertaken => Box::new(CarsOvertaken),
+            FitnessKind::SurvivalTime => Box::new(SurvivalTime),
+            FitnessKind::LaneKeeping => Box::new(LaneKeeping),
+            FitnessKind::Smoothness => Box::new(Smoothness),
+        }
+    }
+}
+
+/// Forward distance travelled since the start position
+pub struct Distance;
+
+impl Fitness for Distance {
+    fn score(&self, stats: &CarStats) -> f32 {
+        stats.distance
+    }
+}
+
+pub struct DistanceTimePenalty(pub f32);
+
+impl Fitness for DistanceTimePenalty {
+    fn score(&self, stats: &CarStats) -> f32 {
+        stats.distance - self.0 * stats.time_alive
+    }
+}
+
+/// Amount of traffic cars left behind
+pub struct CarsOvertaken;
+
+impl Fitness for CarsOvertaken {
+    fn score(&self, stats: &CarStats) -> f32 {
+        stats.overtaken.len() as f32
+    }
+}
+
+/// Seconds driven without colliding
+pub struct SurvivalTime;
+
+impl Fitness for SurvivalTime {
+    fn score(&self, stats: &CarStats) -> f32 {
+        stats.time_alive
+    }
+}
+
+/// Average closeness to a lane center, from 0 (on a lane line) to 1 (centered)
+pub struct LaneKeeping;
+
+impl Fitness for LaneKeeping {
+    fn score(&self, stats: &CarStats) -> f32 {
+        if stats.ticks == 0 {
+            return 0.;
+        }
+        stats.lane_keeping / stats.ticks as f32
+    }
+}
+
+/// Average steadiness of the controls, from 0 (flipping every tick) to 1 (never changing)
+pub struct Smoothness;
+
+impl Fitness for Smoothness {
+    fn score(&self, stats: &CarStats) -> f32 {
+        if stats.ticks == 0 {
+            return 0.;
+        }
+        stats.smoothness / stats.ticks as f32
+    }
+}
+
+/// Weighted sum of several fitness functions
+pub struct WeightedFitness(pub Vec<(Box<dyn Fitness + Send + Sync>, f32)>);
+
+impl Fitness for WeightedFitness {
+    fn score(&self, stats: &CarStats) -> f32 {
+        self.0
+            .iter()
+            .map(|(fitness, weight)| fitness.score(stats) * weight)
+            .sum()
+    }
+}
+
+impl From<&[(FitnessKind, f32)]> for WeightedFitness {
+    fn from(kinds: &[(FitnessKind, f32)]) -> Self {
+        Self(
+            kinds
+                .iter()
+                .map(|(kind, weight)| (kind.build(), *weight))
+                .collect(),
+        )
+    }
+}
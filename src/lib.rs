@@ -1,15 +1,46 @@
+mod brain;
 mod components;
+mod config_file;
+mod dataset;
 mod events;
+mod fitness;
 mod query_filters;
+mod replay;
 mod resources;
+mod road;
 mod systems;
+mod training;
 mod utils;
+use bevy::app::AppExit;
 use bevy::prelude::*;
-use events::{ChangeTargetEvent, LoadNetworkEvent};
-use resources::{CameraTarget, Config, NetworkConfig};
-use std::f32::consts::PI;
+use bevy::time::TimeUpdateStrategy;
+use events::{
+    ChangeTargetEvent, GenerationEndedEvent, LoadNetworkEvent, NextGenerationEvent,
+    NotificationEvent,
+};
+use resources::{
+    CameraTarget, EffectiveSpeed, Evaluation, FitnessFunction, FitnessHistory, Generation,
+    HistoryExport, LoadSelection, ManualDriving, Playback, Recorder, ReplayRecorder, SaveEncoding,
+    SimulationRng, SimulationStats, StartupBrain, WatchedConfig,
+};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-pub use resources::WindowSize;
+pub use brain::{
+    export_brain, list_brains, read_brain, write_brain, Brain, BrainEncoding, BrainEntry,
+    BrainMetadata, BrainSelection, PersistenceError, BRAINS_DIR, BRAIN_FORMAT_VERSION,
+    JSON_SCHEMA_VERSION,
+};
+pub use components::{Activation, Crossover};
+pub use config_file::{ConfigError, ConfigFile};
+pub use dataset::{read_dataset, Dataset, DatasetError, DatasetWriter, Sample, DATASETS_DIR};
+pub use fitness::FitnessKind;
+pub use replay::{
+    read_replay, write_replay, Replay, ReplayError, KEYFRAME_INTERVAL, REPLAY_VERSION,
+};
+pub use resources::{BroadPhase, Config, NetworkConfig, RoadProperties, WindowSize};
+pub use road::RoadPath;
+pub use training::{train, train_brain, Optimizer, TrainingConfig, TrainingError};
 
 const FIXED_DELTA: f32 = 1.0 / 60.0;
 
@@ -18,80 +49,241 @@ enum AppState {
     #[default]
     Running,
     LoadingNetwork,
+    NextGeneration,
 }
 
-pub struct SelfDrivingCar;
+/// What the simulation is launched for
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum RunMode {
+    /// Evolves the population generation after generation
+    #[default]
+    Train,
+    /// Drives a saved brain over and over without evolving it
+    Watch(PathBuf),
+    /// Drives a saved brain for a number of episodes and reports its fitness
+    Evaluate { brain: PathBuf, episodes: u32 },
+    /// Plays back a generation recorded as a replay, without simulating anything
+    Replay(PathBuf),
+}
+
+#[derive(Default)]
+pub struct SelfDrivingCar {
+    pub config: Config,
+    pub network_config: NetworkConfig,
+    /// Lanes and width of the road, as wide as the window when `None`
+    pub road: Option<RoadProperties>,
+    /// Configuration file whose non-structural values are reloaded when it changes
+    pub config_path: Option<PathBuf>,
+    pub mode: RunMode,
+    /// CSV file rewritten with the fitness history after every generation
+    pub history_path: Option<PathBuf>,
+    /// Folder every generation is recorded into as a replay
+    pub replays_dir: Option<PathBuf>,
+    /// Runs only the simulation, without rendering or UI, one fixed step per update
+    pub headless: bool,
+}
 
 impl Plugin for SelfDrivingCar {
     fn build(&self, app: &mut App) {
-        let initial_config = Config {
-            max_traffic: 18,
-            controlllable_cars: 250,
-            ..Default::default()
-        };
-        let network_config = NetworkConfig {
-            input_neuron_count: 18,
-            input_ray_length: 130.0,
-            input_ray_spread: PI * 0.9,
-            mutate_factor: 0.075,
-            hidden_layers: 2,
-            hidden_layers_neuron_count: 9,
-            output_neuron_count: 4,
-        };
+        let mut initial_config = self.config.clone();
+        let mut network_config = self.network_config.clone();
+        match &self.mode {
+            RunMode::Train => {
+                if let Some(path) = &self.config_path {
+                    app.insert_resource(WatchedConfig::new(path.clone()))
+                        .add_systems(Update, systems::config::hot_reload);
+                }
+            }
+            RunMode::Watch(brain) | RunMode::Evaluate { brain, .. } => {
+                // A single car driving the unchanged brain every generation
+                initial_config.controlllable_cars = 1;
+                initial_config.survivors = 1;
+                initial_config.crossover = None;
+                network_config.mutate_factor = 0.;
+                app.insert_resource(StartupBrain(BrainSelection::from_arg(brain)));
+            }
+            RunMode::Replay(path) => {
+                build_playback(app, path);
+                return;
+            }
+        }
+        if let RunMode::Evaluate { episodes, .. } = self.mode {
+            app.insert_resource(Evaluation {
+                episodes,
+                ..Default::default()
+            });
+        }
+
+        let rng = SimulationRng::new(initial_config.seed.unwrap_or_else(rand::random));
+        info!("Simulation seed: {}", rng.seed());
+
+        if let Some(road) = self.road {
+            app.insert_resource(road);
+        }
+        if let Some(path) = &self.history_path {
+            app.insert_resource(HistoryExport(path.clone()));
+        }
+        if let Some(dir) = &self.replays_dir {
+            app.insert_resource(ReplayRecorder::new(dir.clone()))
+                .add_systems(
+                    FixedUpdate,
+                    (systems::replay::record)
+                        .before(systems::car::move_cars)
+                        .run_if(state_exists_and_equals(AppState::Running)),
+                )
+                .add_systems(
+                    Update,
+                    (systems::replay::save).before(systems::generation::next_generation),
+                );
+        }
 
         app.insert_resource(FixedTime::new_from_secs(FIXED_DELTA))
+            .insert_resource(rng)
+            .insert_resource(FitnessFunction::from_config(&initial_config))
             .insert_resource(initial_config)
             .insert_resource(network_config)
             .insert_resource(CameraTarget::default())
+            .init_resource::<LoadSelection>()
+            .init_resource::<SaveEncoding>()
+            .init_resource::<BroadPhase>()
+            .init_resource::<FitnessHistory>()
+            .insert_resource(Generation::default())
             .init_resource::<State<AppState>>();
 
         app.register_type::<components::NetworkLevel>()
+            .register_type::<components::Activation>()
             .register_type::<Vec<components::NetworkLevel>>()
             .register_type::<Vec<f32>>()
             .register_type::<Vec<Vec<f32>>>();
 
         app.add_event::<LoadNetworkEvent>()
-            .add_event::<ChangeTargetEvent>();
+            .add_event::<ChangeTargetEvent>()
+            .add_event::<NextGenerationEvent>()
+            .add_event::<GenerationEndedEvent>()
+            .add_event::<NotificationEvent>();
 
         app.add_systems(
             Startup,
             (
-                systems::ui::setup,
                 systems::road::setup,
                 systems::car::setup,
+                (systems::car::load_startup_brain).run_if(resource_exists::<StartupBrain>()),
             )
                 .chain(),
         );
-        // Uncomment the next line to enable keyboard input
-        //app.add_systems(PreUpdate, keyboard_input::read_input);
+        app.add_systems(
+            Update,
+            (systems::generation::record_evaluation).run_if(resource_exists::<Evaluation>()),
+        );
         app.add_systems(
             Update,
             (
-                systems::ray_cast::update_sprites,
-                (systems::car::update_camera_target).in_set(CollisionSystemSet),
-                systems::ui::save_handler,
-                systems::ui::load_handler,
+                systems::history::record,
+                (systems::history::export).run_if(resource_exists::<HistoryExport>()),
             )
-                .run_if(state_exists_and_equals(AppState::Running)),
+                .chain(),
+        );
+        if self.headless {
+            // Every update advances the time by exactly one fixed step, so the simulation
+            // runs as fast as the update loop allows
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                FIXED_DELTA,
+            )));
+        } else {
+            app.init_resource::<SimulationStats>()
+                .init_resource::<EffectiveSpeed>()
+                .init_resource::<ManualDriving>()
+                .init_resource::<Recorder>();
+            app.add_systems(PreUpdate, systems::keyboard_input::read_input);
+            app.add_systems(
+                Startup,
+                (
+                    systems::ui::setup,
+                    systems::hud::setup,
+                    systems::network_panel::setup,
+                    systems::history_chart::setup,
+                    systems::time_control::setup,
+                    systems::manual_driving::setup,
+                ),
+            );
+            app.add_systems(
+                FixedUpdate,
+                (
+                    systems::hud::count_tick,
+                    systems::time_control::cap_steps,
+                    (systems::recorder::record).after(systems::ray_cast::cast_rays),
+                ),
+            );
+            app.add_systems(
+                Update,
+                (
+                    systems::network_panel::update,
+                    systems::hud::measure_rates,
+                    (systems::hud::update).after(systems::hud::measure_rates),
+                    (systems::history_chart::update).after(systems::history::record),
+                    systems::history_chart::export_handler,
+                    systems::time_control::keyboard,
+                    systems::time_control::buttons,
+                    systems::time_control::update_pause_label,
+                    systems::time_control::start_frame,
+                    systems::manual_driving::keyboard,
+                    systems::manual_driving::button,
+                    systems::recorder::toggle,
+                    systems::ui::show_notifications,
+                    systems::ui::expire_notifications,
+                ),
+            );
+            app.add_systems(
+                Update,
+                (
+                    systems::ray_cast::update_sprites,
+                    systems::ui::save_handler,
+                    systems::ui::load_handler,
+                    systems::ui::browse_handler,
+                    systems::ui::encoding_handler,
+                    systems::manual_driving::sync,
+                )
+                    .run_if(state_exists_and_equals(AppState::Running)),
+            );
+        }
+        app.add_systems(
+            Update,
+            (
+                systems::car::load_network,
+                systems::car::reset_traffic,
+                systems::road::reset_road,
+            )
+                .chain()
+                .run_if(state_exists_and_equals(AppState::LoadingNetwork)),
         );
         app.add_systems(
             Update,
-            (systems::car::load_network).run_if(state_exists_and_equals(AppState::LoadingNetwork)),
+            (
+                systems::generation::next_generation,
+                systems::car::reset_traffic,
+                systems::road::reset_road,
+            )
+                .chain()
+                .run_if(state_exists_and_equals(AppState::NextGeneration)),
         );
         app.add_systems(
             FixedUpdate,
             (
                 systems::car::move_cars,
                 (systems::car::find_new_camera_target).before(CollisionSystemSet),
+                // Switching targets within the fixed step keeps the camera, and thus
+                // the traffic around it, independent from the frame rate
+                (systems::car::update_camera_target).in_set(CollisionSystemSet),
                 systems::car::despawn_traffic,
                 systems::car::spawn_traffic,
-                (
-                    (systems::car::check_collisions).in_set(CollisionSystemSet),
-                    systems::road::move_road,
-                )
-                    .chain(),
+                systems::road::move_road,
+                // Indexed once the road pieces have wrapped around, both passes share it
+                systems::ray_cast::update_broad_phase,
+                (systems::car::check_collisions).in_set(CollisionSystemSet),
                 (systems::ray_cast::cast_rays).in_set(CollisionSystemSet),
                 systems::network::update,
+                systems::fitness::evaluate,
+                systems::generation::check_generation_end,
             )
                 .chain()
                 .run_if(state_exists_and_equals(AppState::Running)),
@@ -101,3 +293,138 @@ impl Plugin for SelfDrivingCar {
 
 #[derive(SystemSet, Debug, Hash, Clone, PartialEq, Eq)]
 struct CollisionSystemSet;
+
+/// Shows the replay at `path` with the road it was recorded on, exiting when it can't be read
+fn build_playback(app: &mut App, path: &Path) {
+    let replay = match read_replay(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            error!("Couldn't load the replay {}: {e}", path.display());
+            app.add_systems(Startup, |mut ev_exit: EventWriter<AppExit>| {
+                ev_exit.send(AppExit)
+            });
+            return;
+        }
+    };
+    info!(
+        "Replaying generation {} of seed {}",
+        replay.generation, replay.seed
+    );
+
+    let path = replay.road_path();
+    app.insert_resource(FixedTime::new_from_secs(replay.delta))
+        .insert_resource(replay.road)
+        .insert_resource(Playback::new(replay, &path))
+        .insert_resource(path)
+        .init_resource::<SimulationStats>()
+        .init_resource::<EffectiveSpeed>();
+    app.add_systems(
+        Startup,
+        (
+            systems::road::setup,
+            systems::playback::setup,
+            systems::time_control::setup,
+        ),
+    );
+    app.add_systems(
+        FixedUpdate,
+        (
+            systems::playback::advance,
+            systems::hud::count_tick,
+            systems::time_control::cap_steps,
+        ),
+    );
+    app.add_systems(
+        Update,
+        (
+            systems::time_control::keyboard,
+            systems::time_control::buttons,
+            systems::time_control::update_pause_label,
+            systems::time_control::start_frame,
+            systems::hud::measure_rates,
+            (
+                systems::playback::keyboard,
+                systems::playback::sync,
+                systems::playback::follow,
+                systems::playback::update_text,
+            )
+                .chain(),
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use components::{FitnessScore, NeuralNetwork, TrafficCar};
+
+    /// Everything that sets a generation apart: the networks, the traffic and the scores
+    #[derive(Debug, PartialEq)]
+    struct Snapshot {
+        generation: u32,
+        /// Weights and biases of every network
+        networks: Vec<Vec<f32>>,
+        traffic: Vec<[f32; 7]>,
+        fitness: Vec<f32>,
+    }
+
+    fn run_headless(seed: u64, steps: usize) -> Snapshot {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .insert_resource(WindowSize(400., 600.))
+            .add_plugins(SelfDrivingCar {
+                config: Config {
+                    seed: Some(seed),
+                    controlllable_cars: 30,
+                    max_traffic: 6,
+                    // Short generations, so the run breeds a new one
+                    max_generation_duration: 3.,
+                    ..Config::default()
+                },
+                headless: true,
+                ..Default::default()
+            });
+        for _ in 0..steps {
+            app.update();
+        }
+
+        let world = &mut app.world;
+        let networks = (world.query::<&NeuralNetwork>().iter(world))
+            .map(|network| {
+                (network.levels.iter())
+                    .flat_map(|level| level.weights.iter().flatten().chain(&level.biases))
+                    .copied()
+                    .collect()
+            })
+            .collect();
+        let traffic = (world
+            .query_filtered::<&Transform, With<TrafficCar>>()
+            .iter(world))
+        .map(|transform| {
+            let (t, r) = (transform.translation, transform.rotation);
+            [t.x, t.y, t.z, r.x, r.y, r.z, r.w]
+        })
+        .collect();
+        let fitness = (world.query::<&FitnessScore>().iter(world))
+            .map(|score| score.0)
+            .collect();
+        Snapshot {
+            generation: world.resource::<Generation>().count,
+            networks,
+            traffic,
+            fitness,
+        }
+    }
+
+    #[test]
+    fn a_seed_replays_the_same_generations() {
+        let first = run_headless(3, 300);
+        assert!(
+            first.generation >= 1,
+            "the run should reach a second generation"
+        );
+        assert!(!first.networks.is_empty() && !first.traffic.is_empty());
+        assert_eq!(first, run_headless(3, 300));
+        assert_ne!(first.networks, run_headless(4, 300).networks);
+    }
+}
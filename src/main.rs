@@ -1,12 +1,72 @@
+mod cli;
+
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::log;
 use bevy::prelude::*;
 use bevy::window::{ExitCondition, WindowResolution};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use selfdriving_car::{
+    export_brain, read_brain, read_dataset, train_brain, write_brain, BrainEncoding,
+    BrainSelection, NetworkConfig, TrainingConfig, BRAINS_DIR,
+};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 fn main() {
-    let window_size = selfdriving_car::WindowSize(400., 600.);
+    let cli = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Simulate(cli)) => cli,
+        Ok(cli::Command::Convert { brain, output }) => {
+            let converted = BrainSelection::from_arg(&brain)
+                .resolve(BRAINS_DIR)
+                .and_then(read_brain)
+                .and_then(|brain| export_brain(&output, &brain));
+            if let Err(e) = converted {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+            println!("Wrote {}", output.display());
+            return;
+        }
+        Ok(cli::Command::Fit {
+            dataset,
+            network,
+            training,
+            from,
+            seed,
+        }) => {
+            match fit(&dataset, network, &training, from.as_deref(), seed) {
+                Ok(path) => println!("Wrote {}", path.display()),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Err(cli::CliError::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let window_size = cli.window_size;
 
-    App::new()
-        .add_plugins(
+    let mut app = App::new();
+    if cli.plugin.headless {
+        app.add_plugins((
+            MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)),
+            log::LogPlugin {
+                filter: "info".into(),
+                level: log::Level::INFO,
+            },
+        ));
+    } else {
+        app.add_plugins(
             DefaultPlugins
                 .set(WindowPlugin {
                     primary_window: Some(Window {
@@ -23,8 +83,49 @@ fn main() {
                     level: log::Level::DEBUG,
                 })
                 .build(),
-        )
-        .insert_resource(window_size)
-        .add_plugins(selfdriving_car::SelfDrivingCar)
+        );
+    }
+    app.insert_resource(window_size)
+        .add_plugins(cli.plugin)
         .run();
 }
+
+/// Trains a network on the recorded driving and saves it to the library
+fn fit(
+    dataset: &Path,
+    mut network: NetworkConfig,
+    training: &TrainingConfig,
+    from: Option<&Path>,
+    seed: Option<u64>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let dataset = read_dataset(dataset)?;
+    println!(
+        "{} samples of {} rays",
+        dataset.samples.len(),
+        dataset.rays()
+    );
+    let levels = match from {
+        Some(brain) => {
+            let brain = read_brain(BrainSelection::from_arg(brain).resolve(BRAINS_DIR)?)?;
+            network = brain.metadata.network;
+            Some(brain.levels)
+        }
+        None => {
+            network.input_neuron_count = u8::try_from(dataset.rays())?;
+            None
+        }
+    };
+
+    let seed = seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let brain = train_brain(
+        &dataset,
+        network,
+        levels,
+        training,
+        seed,
+        &mut rng,
+        |epoch, loss| println!("Epoch {:>4}: loss {loss:.5}", epoch + 1),
+    )?;
+    Ok(write_brain(BRAINS_DIR, &brain, BrainEncoding::Ron)?)
+}
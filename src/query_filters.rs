@@ -6,6 +6,12 @@ pub(super) type ControllableCar = (
     With<components::Controls>,
     Without<components::CarCollided>,
 );
+/// Cars driven by a network, collided ones included
+pub(super) type Population = (With<components::Car>, With<components::NeuralNetwork>);
+pub(super) type HumanCar = (
+    With<components::HumanDriver>,
+    Without<components::CarCollided>,
+);
 pub(super) type Collider = (
     With<components::StaticCollider>,
     Without<components::Controls>,
@@ -15,16 +21,14 @@ pub(super) type Traffic = (
     With<components::TrafficCar>,
     Without<components::Controls>,
 );
-pub(super) type Pavement = (
-    With<components::Pavement>,
-    Without<Camera2d>,
-    Without<components::RoadLine>,
-);
 pub(super) type CameraTarget = (
     With<components::CameraFollowMarker>,
     Without<Camera2d>,
-    Without<components::RoadLine>,
-    Without<components::Pavement>,
+    Without<components::RoadPiece>,
+);
+pub(super) type FollowedCar = (
+    With<components::CameraFollowMarker>,
+    Without<components::CarCollided>,
 );
 pub(super) type SaveButton = (
     Changed<Interaction>,
@@ -36,3 +40,23 @@ pub(super) type LoadButton = (
     With<Button>,
     With<components::LoadButton>,
 );
+pub(super) type BrowseButton = (
+    Changed<Interaction>,
+    With<Button>,
+    With<components::BrowseButton>,
+);
+pub(super) type ExportHistoryButton = (
+    Changed<Interaction>,
+    With<Button>,
+    With<components::ExportHistoryButton>,
+);
+pub(super) type ManualDrivingButton = (
+    Changed<Interaction>,
+    With<Button>,
+    With<components::ManualDrivingButton>,
+);
+pub(super) type EncodingButton = (
+    Changed<Interaction>,
+    With<Button>,
+    With<components::EncodingButton>,
+);
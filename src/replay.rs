@@ -0,0 +1,556 @@
+//! Recordings of whole generations: the seed and settings they ran with, the brain of every
+//! car, and what happened on every fixed tick, enough to drive the scene again exactly as it
+//! was simulated.
+//!
+//! A tick lists the traffic spawned and despawned and the cars that crashed since the
+//! previous tick, then the controls every car still driving moves with. Keyframes hold the
+//! whole scene every [`KEYFRAME_INTERVAL`] ticks so any tick is reached by re-running at most
+//! that many ticks, which makes seeking and rewinding cheap. Cars driven by a human aren't
+//! recorded, and the road is generated again from the seed.
+
+use crate::brain::Level;
+use crate::components::{Car, Controls, NetworkError, NetworkLevel, NeuralNetwork, TrafficCar};
+use crate::resources::{Config, NetworkConfig, RoadProperties};
+use crate::road::RoadPath;
+use bevy::prelude::{Quat, Transform, Vec3};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Ticks between two keyframes, a second of simulation
+pub const KEYFRAME_INTERVAL: usize = 60;
+/// Version of the replay files, bumped on every change to their layout
+pub const REPLAY_VERSION: u32 = 2;
+/// Maximum speed of the controllable cars, traffic cars storing theirs
+const CAR_MAX_SPEED: f32 = 150.;
+
+/// A recorded generation
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub generation: u32,
+    /// Length of a tick in seconds
+    pub delta: f32,
+    pub config: Config,
+    pub network: NetworkConfig,
+    pub road: RoadProperties,
+    /// Levels of every car, which is identified by its index in every tick and keyframe
+    pub(crate) brains: Vec<Vec<Level>>,
+    pub ticks: Vec<Tick>,
+    /// Scene at the start of every [`KEYFRAME_INTERVAL`]th tick
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// Changes to the scene since the previous tick, and how the cars move during the tick
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Tick {
+    pub spawned: Vec<TrafficState>,
+    /// Ids of the traffic cars gone since the previous tick
+    pub despawned: Vec<u32>,
+    /// Indices of the cars that crashed since the previous tick
+    pub crashed: Vec<usize>,
+    /// Index, acceleration and turn direction of every car still driving
+    pub controls: Vec<(usize, f32, f32)>,
+}
+
+/// Whole scene at the start of a tick
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Keyframe {
+    /// Every car, crashed ones included, by index
+    pub cars: Vec<CarState>,
+    pub crashed: Vec<bool>,
+    pub fitness: Vec<f32>,
+    pub traffic: Vec<TrafficState>,
+}
+
+/// What a car needs to keep driving as it did, the rotation being stored as is so driving
+/// again gives the very same values
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct CarState {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub speed: f32,
+    pub handling: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct TrafficState {
+    /// Identifies the traffic car within the replay
+    pub id: u32,
+    pub max_speed: f32,
+    pub lane: u8,
+    /// Arc length driven along the road
+    pub progress: f32,
+    pub state: CarState,
+}
+
+impl CarState {
+    pub fn new(car: &Car, transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+            speed: car.speed,
+            handling: car.handling,
+        }
+    }
+
+    fn restore(&self, max_speed: f32) -> (Car, Transform) {
+        let mut car = Car::new(max_speed);
+        car.speed = self.speed;
+        car.handling = self.handling;
+        let transform = Transform {
+            translation: Vec3::from_array(self.translation),
+            rotation: Quat::from_array(self.rotation),
+            ..Default::default()
+        };
+        (car, transform)
+    }
+}
+
+impl Replay {
+    /// Last tick of the replay, the scene when the generation ended
+    pub fn last_tick(&self) -> usize {
+        self.ticks.len().saturating_sub(1)
+    }
+
+    /// Road the replay was recorded on, generated past everywhere its cars drive
+    pub fn road_path(&self) -> RoadPath {
+        let mut path = RoadPath::new(self.seed, self.road, self.config.road_curviness);
+        let furthest_traffic = self
+            .keyframes
+            .iter()
+            .flat_map(|keyframe| &keyframe.traffic)
+            .chain(self.ticks.iter().flat_map(|tick| &tick.spawned))
+            .map(|traffic| traffic.progress)
+            .fold(0., f32::max);
+        path.extend_to(furthest_traffic + self.ticks.len() as f32 * self.delta * CAR_MAX_SPEED);
+        path
+    }
+
+    /// Scene at the start of `tick`, or of the last tick
+    pub fn scene_at(&self, tick: usize, path: &RoadPath) -> Scene {
+        let tick = tick.min(self.last_tick());
+        let keyframe = (tick / KEYFRAME_INTERVAL).min(self.keyframes.len().saturating_sub(1));
+        let mut scene =
+            Scene::from_keyframe(&self.keyframes[keyframe], keyframe * KEYFRAME_INTERVAL);
+        while scene.tick < tick {
+            scene.advance(self, path);
+        }
+        scene
+    }
+
+    /// Network levels of the car with the given index
+    pub fn levels(&self, car: usize) -> Vec<NetworkLevel> {
+        self.brains[car]
+            .iter()
+            .cloned()
+            .map(NetworkLevel::from)
+            .collect()
+    }
+
+    /// Fitness of the car at the last keyframe before `tick`
+    pub fn fitness(&self, car: usize, tick: usize) -> f32 {
+        let keyframe = (tick / KEYFRAME_INTERVAL).min(self.keyframes.len().saturating_sub(1));
+        self.keyframes
+            .get(keyframe)
+            .and_then(|keyframe| keyframe.fitness.get(car))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}-{:04}.ron", self.seed, self.generation)
+    }
+}
+
+/// A car of the scene being replayed
+pub struct SceneCar {
+    pub car: Car,
+    pub transform: Transform,
+    pub crashed: bool,
+}
+
+pub struct SceneTraffic {
+    pub id: u32,
+    pub car: Car,
+    pub traffic_car: TrafficCar,
+    pub transform: Transform,
+}
+
+/// Scene of a replay at the start of a tick, driven again from a keyframe
+pub struct Scene {
+    pub tick: usize,
+    pub cars: Vec<SceneCar>,
+    pub traffic: Vec<SceneTraffic>,
+}
+
+impl Scene {
+    fn from_keyframe(keyframe: &Keyframe, tick: usize) -> Self {
+        let cars = keyframe
+            .cars
+            .iter()
+            .zip(&keyframe.crashed)
+            .map(|(state, crashed)| {
+                let (car, transform) = state.restore(CAR_MAX_SPEED);
+                SceneCar {
+                    car,
+                    transform,
+                    crashed: *crashed,
+                }
+            })
+            .collect();
+        let mut scene = Self {
+            tick,
+            cars,
+            traffic: Vec::new(),
+        };
+        scene.spawn_traffic(&keyframe.traffic);
+        scene
+    }
+
+    fn spawn_traffic(&mut self, traffic: &[TrafficState]) {
+        self.traffic.extend(traffic.iter().map(|traffic| {
+            let (car, transform) = traffic.state.restore(traffic.max_speed);
+            SceneTraffic {
+                id: traffic.id,
+                car,
+                traffic_car: TrafficCar {
+                    lane: traffic.lane,
+                    progress: traffic.progress,
+                },
+                transform,
+            }
+        }));
+    }
+
+    /// Drives every car through the current tick and applies the changes recorded for the
+    /// next one, returning false when the replay is already over
+    pub fn advance(&mut self, replay: &Replay, path: &RoadPath) -> bool {
+        if self.tick >= replay.last_tick() {
+            return false;
+        }
+        // Same order as the simulation, which moves the traffic along with the cars
+        for &(index, acceleration, turn_direction) in &replay.ticks[self.tick].controls {
+            let controls = Controls {
+                acceleration,
+                turn_direction,
+            };
+            if let Some(car) = self.cars.get_mut(index) {
+                car.car
+                    .drive(&mut car.transform, Some(&controls), replay.delta);
+            }
+        }
+        for traffic in &mut self.traffic {
+            traffic.traffic_car.drive(
+                &mut traffic.car,
+                &mut traffic.transform,
+                replay.road,
+                path,
+                replay.delta,
+            );
+        }
+
+        self.tick += 1;
+        let tick = &replay.ticks[self.tick];
+        self.traffic
+            .retain(|traffic| !tick.despawned.contains(&traffic.id));
+        self.spawn_traffic(&tick.spawned);
+        for &index in &tick.crashed {
+            if let Some(car) = self.cars.get_mut(index) {
+                car.crashed = true;
+            }
+        }
+        true
+    }
+}
+
+/// Reasons a replay couldn't be saved or loaded
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(PathBuf, std::io::Error),
+    Serialize(String),
+    Parse(String),
+    UnsupportedVersion(u32),
+    /// The brain of the car with this index can't form a working network
+    InvalidBrain(usize, NetworkError),
+    /// The replay has no tick, or misses the keyframes of some
+    Incomplete,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ReplayError::Serialize(e) => write!(f, "can't serialize the replay: {e}"),
+            ReplayError::Parse(e) => write!(f, "not a valid replay: {e}"),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "replay version {version} can't be read, only version {REPLAY_VERSION} is supported"
+            ),
+            ReplayError::InvalidBrain(car, e) => write!(f, "invalid brain of car {car}: {e}"),
+            ReplayError::Incomplete => write!(f, "the replay misses ticks or keyframes"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// The version alone, checked before the rest of a layout that may differ from the current one
+#[derive(Deserialize)]
+struct Probe {
+    version: u32,
+}
+
+/// Reads a replay written with [`write_replay`]
+pub fn read_replay(path: impl AsRef<Path>) -> Result<Replay, ReplayError> {
+    let path = path.as_ref();
+    let replay_serialized =
+        std::fs::read_to_string(path).map_err(|e| ReplayError::Io(path.to_path_buf(), e))?;
+    let probe: Probe =
+        ron::from_str(&replay_serialized).map_err(|e| ReplayError::Parse(e.to_string()))?;
+    if probe.version != REPLAY_VERSION {
+        return Err(ReplayError::UnsupportedVersion(probe.version));
+    }
+    let replay: Replay =
+        ron::from_str(&replay_serialized).map_err(|e| ReplayError::Parse(e.to_string()))?;
+    if replay.ticks.is_empty()
+        || replay.keyframes.len() != replay.ticks.len().div_ceil(KEYFRAME_INTERVAL)
+        || replay.keyframes.iter().any(|keyframe| {
+            keyframe.cars.len() != replay.brains.len()
+                || keyframe.crashed.len() != keyframe.cars.len()
+                || keyframe.fitness.len() != keyframe.cars.len()
+        })
+    {
+        return Err(ReplayError::Incomplete);
+    }
+    for car in 0..replay.brains.len() {
+        NeuralNetwork::validate(&replay.levels(car))
+            .map_err(|e| ReplayError::InvalidBrain(car, e))?;
+    }
+    Ok(replay)
+}
+
+/// Writes the replay into `dir`, returning the path of the new file
+pub fn write_replay(dir: impl AsRef<Path>, replay: &Replay) -> Result<PathBuf, ReplayError> {
+    let dir = dir.as_ref();
+    let path = dir.join(replay.file_name());
+    let replay_serialized =
+        ron::to_string(replay).map_err(|e| ReplayError::Serialize(e.to_string()))?;
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&path, replay_serialized))
+        .map_err(|e| ReplayError::Io(path.clone(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Activation;
+    use crate::utils::TempDir;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const TICKS: usize = 150;
+
+    fn car_state(translation: Vec3, rotation: Quat) -> CarState {
+        CarState {
+            translation: translation.to_array(),
+            rotation: rotation.to_array(),
+            speed: 0.,
+            handling: 0.,
+        }
+    }
+
+    /// Keyframe of the scene, the fitness being how far up the cars are
+    fn snapshot(scene: &Scene) -> Keyframe {
+        Keyframe {
+            cars: (scene.cars.iter())
+                .map(|car| CarState::new(&car.car, &car.transform))
+                .collect(),
+            crashed: scene.cars.iter().map(|car| car.crashed).collect(),
+            fitness: (scene.cars.iter())
+                .map(|car| car.transform.translation.y)
+                .collect(),
+            traffic: (scene.traffic.iter())
+                .map(|traffic| TrafficState {
+                    id: traffic.id,
+                    max_speed: traffic.car.max_speed,
+                    lane: traffic.traffic_car.lane,
+                    progress: traffic.traffic_car.progress,
+                    state: CarState::new(&traffic.car, &traffic.transform),
+                })
+                .collect(),
+        }
+    }
+
+    /// Serialized, so every float is compared exactly
+    fn assert_same(a: &Keyframe, b: &Keyframe) {
+        assert_eq!(ron::to_string(a).unwrap(), ron::to_string(b).unwrap());
+    }
+
+    fn traffic(
+        id: u32,
+        lane: u8,
+        progress: f32,
+        road: RoadProperties,
+        path: &RoadPath,
+    ) -> TrafficState {
+        let transform = path.transform_at(progress, road.lane_offset(lane));
+        TrafficState {
+            id,
+            max_speed: 90.,
+            lane,
+            progress,
+            state: car_state(transform.translation, transform.rotation),
+        }
+    }
+
+    /// Two cars weaving along a curved road, the second crashing halfway, while a traffic
+    /// car spawns and another one leaves. The keyframes are recorded by driving the scene
+    /// from the first one, as the simulation would have.
+    fn recorded_replay() -> (Replay, RoadPath) {
+        let mut rng = StdRng::seed_from_u64(2);
+        let road = RoadProperties {
+            lane_count: 3,
+            width: 300.,
+        };
+        let config = Config {
+            road_curviness: 1.,
+            ..Config::default()
+        };
+        let mut path = RoadPath::new(9, road, config.road_curviness);
+        path.extend_to(5000.);
+        let mut brain = || {
+            vec![Level::from(&NetworkLevel::new(
+                5,
+                4,
+                Activation::Step,
+                &mut rng,
+            ))]
+        };
+        let brains = vec![brain(), brain()];
+
+        let mut ticks = vec![Tick::default(); TICKS];
+        for (index, tick) in ticks.iter_mut().enumerate() {
+            let turn = if index / 20 % 2 == 0 { 1. } else { -1. };
+            tick.controls.push((0, 1., turn));
+            if index < 90 {
+                tick.controls.push((1, 1., -turn * 0.5));
+            }
+        }
+        ticks[70].spawned.push(traffic(1, 2, 900., road, &path));
+        ticks[90].crashed.push(1);
+        ticks[100].despawned.push(0);
+
+        let start = Keyframe {
+            cars: vec![
+                car_state(Vec3::new(-80., 0., 0.), Quat::IDENTITY),
+                car_state(Vec3::new(80., 0., 0.), Quat::IDENTITY),
+            ],
+            crashed: vec![false; 2],
+            fitness: vec![0.; 2],
+            traffic: vec![traffic(0, 0, 400., road, &path)],
+        };
+        let mut replay = Replay {
+            version: REPLAY_VERSION,
+            seed: 9,
+            generation: 3,
+            delta: 1. / 60.,
+            config,
+            network: NetworkConfig::default(),
+            road,
+            brains,
+            ticks,
+            keyframes: vec![start.clone()],
+        };
+
+        let mut scene = Scene::from_keyframe(&start, 0);
+        let mut keyframes = vec![start];
+        while scene.advance(&replay, &path) {
+            if scene.tick.is_multiple_of(KEYFRAME_INTERVAL) {
+                keyframes.push(snapshot(&scene));
+            }
+        }
+        replay.keyframes = keyframes;
+        (replay, path)
+    }
+
+    #[test]
+    fn seeks_to_the_recorded_keyframes() {
+        let (replay, path) = recorded_replay();
+        assert_eq!(replay.keyframes.len(), 3);
+        for (k, keyframe) in replay.keyframes.iter().enumerate() {
+            let scene = replay.scene_at(k * KEYFRAME_INTERVAL, &path);
+            assert_eq!(scene.tick, k * KEYFRAME_INTERVAL);
+            assert_same(&snapshot(&scene), keyframe);
+        }
+        // Past the last keyframe the scene is driven from it
+        let end = replay.scene_at(TICKS + 10, &path);
+        assert_eq!(end.tick, replay.last_tick());
+        assert!(end.cars[1].crashed && !end.cars[0].crashed);
+        assert_eq!(end.traffic.iter().map(|t| t.id).collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn seeking_back_and_forth_gives_the_same_scene() {
+        let (replay, path) = recorded_replay();
+        let forward = snapshot(&replay.scene_at(140, &path));
+        assert_same(&snapshot(&replay.scene_at(10, &path)), &{
+            let mut scene = replay.scene_at(0, &path);
+            (0..10).for_each(|_| {
+                scene.advance(&replay, &path);
+            });
+            snapshot(&scene)
+        });
+        assert_same(&snapshot(&replay.scene_at(140, &path)), &forward);
+
+        // Driving tick by tick across the keyframes ends up where seeking does
+        let mut scene = replay.scene_at(10, &path);
+        while scene.tick < 140 {
+            scene.advance(&replay, &path);
+        }
+        assert_same(&snapshot(&scene), &forward);
+    }
+
+    #[test]
+    fn rejects_replays_missing_keyframes() {
+        let dir = TempDir::new("replay");
+        let (mut replay, _) = recorded_replay();
+        let path = write_replay(&dir, &replay).unwrap();
+        assert_eq!(read_replay(&path).unwrap().keyframes.len(), 3);
+
+        replay.keyframes.pop();
+        let path = write_replay(&dir, &replay).unwrap();
+        assert!(matches!(read_replay(&path), Err(ReplayError::Incomplete)));
+
+        let (mut replay, _) = recorded_replay();
+        replay.keyframes[1].cars.pop();
+        let path = write_replay(&dir, &replay).unwrap();
+        assert!(matches!(read_replay(&path), Err(ReplayError::Incomplete)));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let dir = TempDir::new("replay-version");
+        let (replay, _) = recorded_replay();
+        let path = write_replay(&dir, &replay).unwrap();
+        let current = std::fs::read_to_string(&path).unwrap();
+        let prefix = format!("(version:{REPLAY_VERSION},");
+        assert!(current.starts_with(&prefix));
+
+        for version in [REPLAY_VERSION - 1, REPLAY_VERSION + 1] {
+            let other = current.replacen(&prefix, &format!("(version:{version},"), 1);
+            std::fs::write(&path, other).unwrap();
+            match read_replay(&path) {
+                Err(error @ ReplayError::UnsupportedVersion(found)) => {
+                    assert_eq!(found, version);
+                    let message = error.to_string();
+                    assert!(message.contains(&format!("version {version}")));
+                    assert!(message.contains(&format!("version {REPLAY_VERSION}")));
+                }
+                other => panic!("{:?}", other.map(|_| ())),
+            }
+        }
+    }
+}
@@ -1,39 +1,510 @@
-use bevy::prelude::{Entity, Resource};
+use crate::brain::{BrainEncoding, BrainSelection};
+use crate::components::{Activation, Crossover};
+use crate::config_file::ConfigFile;
+use crate::dataset::DatasetWriter;
+use crate::fitness::{Fitness, FitnessKind, WeightedFitness};
+use crate::replay::{Replay, Scene};
+use crate::road::RoadPath;
+use crate::utils::lerp;
+use bevy::prelude::{Entity, Resource, Timer, TimerMode, Transform, Vec2};
+use bevy::utils::HashMap;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
-#[derive(Resource, Default)]
+/// Simulation settings, missing values are taken from [`Config::default`] when deserializing
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub max_traffic: u8,
+    #[serde(skip)]
     pub current_traffic: u8,
+    #[serde(rename = "controllable_cars")]
     pub controlllable_cars: u16,
+    /// How many of the best cars are kept as parents of the next generation
+    pub survivors: u16,
+    /// Simulated seconds after which a generation is ended regardless of its cars
+    pub max_generation_duration: f32,
+    /// Simulated seconds the leading car can go without progress before the generation ends
+    pub stagnation_timeout: f32,
+    /// How the survivors are combined when breeding, cloned when `None`
+    pub crossover: Option<Crossover>,
+    /// Weighted fitness functions used to rank the cars
+    pub fitness: Vec<(FitnessKind, f32)>,
+    /// Seed of every random value in the simulation, picked at random when `None`
+    pub seed: Option<u64>,
+    /// How much the road bends, from 0 for a straight road to 1 for the tightest curves
+    pub road_curviness: f32,
 }
 
-#[derive(Resource, Default)]
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_traffic: 18,
+            current_traffic: 0,
+            controlllable_cars: 250,
+            survivors: 5,
+            max_generation_duration: 90.0,
+            stagnation_timeout: 10.0,
+            crossover: Some(Crossover::Uniform),
+            fitness: vec![(FitnessKind::Distance, 1.0)],
+            seed: None,
+            road_curviness: 0.5,
+        }
+    }
+}
+
+/// Shape and evolution settings of the networks, missing values are taken from
+/// [`NetworkConfig::default`] when deserializing
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NetworkConfig {
+    pub hidden_activation: Activation,
     pub hidden_layers: u8,
     pub hidden_layers_neuron_count: u8,
     pub input_neuron_count: u8,
     pub input_ray_length: f32,
     pub input_ray_spread: f32,
-    #[allow(unused)]
     pub mutate_factor: f32,
+    pub output_activation: Activation,
     pub output_neuron_count: u8,
 }
 
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            hidden_activation: Activation::Step,
+            hidden_layers: 2,
+            hidden_layers_neuron_count: 9,
+            input_neuron_count: 18,
+            input_ray_length: 130.0,
+            input_ray_spread: PI * 0.9,
+            mutate_factor: 0.075,
+            output_activation: Activation::Step,
+            output_neuron_count: 4,
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Neuron count of every network level, from the inputs to the outputs
+    pub fn layers(&self) -> Vec<u8> {
+        let mut layers = vec![self.input_neuron_count];
+        // `hidden_layers` counts the output level too
+        layers.extend((1..self.hidden_layers).map(|_| self.hidden_layers_neuron_count));
+        layers.push(self.output_neuron_count);
+        layers
+    }
+
+    /// Angle of every ray from the front of the car, counterclockwise and in radians,
+    /// ordered as the input neurons they feed
+    pub fn ray_angles(&self) -> Vec<f32> {
+        let a = self.input_ray_spread / 2.;
+        (0..self.input_neuron_count)
+            .map(|i| {
+                let t = if self.input_neuron_count == 1 {
+                    0.5
+                } else {
+                    f32::from(i) / f32::from(self.input_neuron_count - 1)
+                };
+                lerp::<f32, f32>(a, -a, t)
+            })
+            .collect()
+    }
+}
+
+/// Fitness function used for camera targeting, saving and selection
+#[derive(Resource)]
+pub struct FitnessFunction(pub Box<dyn Fitness + Send + Sync>);
+
+impl FitnessFunction {
+    pub fn from_config(config: &Config) -> Self {
+        Self(Box::new(WeightedFitness::from(config.fitness.as_slice())))
+    }
+}
+
+/// Random number generators derived from a single seed. Traffic and evolution
+/// use separate streams so changes in one don't alter the values drawn by the other.
+#[derive(Resource)]
+pub struct SimulationRng {
+    seed: u64,
+    pub traffic: StdRng,
+    pub evolution: StdRng,
+}
+
+impl SimulationRng {
+    const TRAFFIC_STREAM: u64 = 0x7472_6166_6669_6300;
+    const EVOLUTION_STREAM: u64 = 0x6576_6f6c_7574_696f;
+
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            traffic: StdRng::seed_from_u64(seed ^ Self::TRAFFIC_STREAM),
+            evolution: StdRng::seed_from_u64(seed ^ Self::EVOLUTION_STREAM),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the traffic stream so every generation faces a traffic
+    /// that only depends on the seed and the generation number
+    pub fn reset_traffic(&mut self, generation: u32) {
+        self.traffic =
+            StdRng::seed_from_u64(self.seed ^ Self::TRAFFIC_STREAM ^ u64::from(generation));
+    }
+}
+
+/// Keeps track of the current generation lifecycle
+#[derive(Resource, Default, Debug)]
+pub struct Generation {
+    pub count: u32,
+    /// Simulated seconds since the generation started
+    pub elapsed: f32,
+    /// Furthest distance along the road driven by any car of the generation
+    pub best_progress: f32,
+    /// Simulated seconds since `best_progress` last improved
+    pub stagnant_for: f32,
+}
+
+impl Generation {
+    pub fn advance(&mut self) {
+        *self = Self {
+            count: self.count + 1,
+            ..Default::default()
+        };
+    }
+}
+
+/// How the cars of a generation ended up
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrashCounts {
+    pub traffic: u32,
+    pub road_edge: u32,
+    /// Cars still driving when the generation ended
+    pub survived: u32,
+}
+
+/// Fitness statistics of a finished generation
+#[derive(Clone, Debug)]
+pub struct GenerationRecord {
+    pub generation: u32,
+    pub best: f32,
+    pub median: f32,
+    pub mean: f32,
+    pub crashes: CrashCounts,
+    /// Why the generation ended
+    pub end_reason: &'static str,
+}
+
+impl GenerationRecord {
+    /// Statistics of the fitness of every car, sorted best first, none without cars
+    pub fn new(
+        generation: u32,
+        fitness: &[f32],
+        crashes: CrashCounts,
+        end_reason: &'static str,
+    ) -> Option<Self> {
+        let best = *fitness.first()?;
+        let middle = fitness.len() / 2;
+        let median = if fitness.len().is_multiple_of(2) {
+            (fitness[middle - 1] + fitness[middle]) / 2.
+        } else {
+            fitness[middle]
+        };
+        Some(GenerationRecord {
+            generation,
+            best,
+            median,
+            mean: fitness.iter().sum::<f32>() / fitness.len() as f32,
+            crashes,
+            end_reason,
+        })
+    }
+}
+
+/// Every finished generation, oldest first
+#[derive(Resource, Default)]
+pub struct FitnessHistory {
+    pub records: Vec<GenerationRecord>,
+}
+
+impl FitnessHistory {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "generation,best,median,mean,traffic_crashes,road_edge_crashes,survived,end_reason\n",
+        );
+        for record in &self.records {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                record.generation,
+                record.best,
+                record.median,
+                record.mean,
+                record.crashes.traffic,
+                record.crashes.road_edge,
+                record.crashes.survived,
+                record.end_reason
+            ));
+        }
+        csv
+    }
+}
+
+/// File the [`FitnessHistory`] is written to as CSV after every generation
+#[derive(Resource)]
+pub struct HistoryExport(pub PathBuf);
+
+/// Whether a car driven with the arrow keys joins the simulation, it drives the same
+/// traffic as the networks and is respawned with every generation
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ManualDriving {
+    #[default]
+    Off,
+    /// Among the networks
+    WithNetworks,
+    /// The networks keep training but are hidden while the car is driving
+    Alone,
+}
+
+impl ManualDriving {
+    pub fn next(self) -> Self {
+        match self {
+            ManualDriving::Off => ManualDriving::WithNetworks,
+            ManualDriving::WithNetworks => ManualDriving::Alone,
+            ManualDriving::Alone => ManualDriving::Off,
+        }
+    }
+}
+
+impl fmt::Display for ManualDriving {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManualDriving::Off => write!(f, "Drive: off"),
+            ManualDriving::WithNetworks => write!(f, "Drive: with AI"),
+            ManualDriving::Alone => write!(f, "Drive: alone"),
+        }
+    }
+}
+
+/// Records the human driving as demonstrations when enabled, `session` being the car
+/// currently recorded and its file
+#[derive(Resource, Default)]
+pub struct Recorder {
+    pub enabled: bool,
+    pub session: Option<(Entity, DatasetWriter)>,
+}
+
+/// Statistics shown by the HUD that outlive a generation
+#[derive(Resource)]
+pub struct SimulationStats {
+    /// Highest fitness reached by any car since the simulation started
+    pub best_fitness: f32,
+    /// Frames and fixed steps run since the rates were last measured
+    pub frames: u32,
+    pub ticks: u32,
+    pub frames_per_second: f32,
+    pub ticks_per_second: f32,
+    /// Real time between two measures of the rates
+    pub measure: Timer,
+}
+
+impl Default for SimulationStats {
+    fn default() -> Self {
+        Self {
+            best_fitness: 0.,
+            frames: 0,
+            ticks: 0,
+            frames_per_second: 0.,
+            ticks_per_second: 0.,
+            measure: Timer::from_seconds(1., TimerMode::Repeating),
+        }
+    }
+}
+
+/// Keeps the simulation from asking more fixed steps of a frame than it can run. The speed
+/// it really reaches follows from the tick rate of the [`SimulationStats`].
+#[derive(Resource, Default)]
+pub struct EffectiveSpeed {
+    /// Fixed steps run during the current frame
+    pub frame_steps: u32,
+}
+
+/// Brain loaded into the population as soon as the simulation starts
+#[derive(Resource)]
+pub struct StartupBrain(pub BrainSelection);
+
+/// Brain loaded by the Load button
+#[derive(Resource, Default)]
+pub struct LoadSelection(pub BrainSelection);
+
+/// Encoding of the brains saved by the Save button
+#[derive(Resource, Default)]
+pub struct SaveEncoding(pub BrainEncoding);
+
+/// Generation being recorded as a replay, written into `dir` once it ends
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub dir: PathBuf,
+    pub replay: Option<Replay>,
+    /// Index of every recorded car
+    pub cars: HashMap<Entity, usize>,
+    pub crashed: Vec<bool>,
+    /// Id of every traffic car recorded so far
+    pub traffic: HashMap<Entity, u32>,
+    pub next_traffic_id: u32,
+}
+
+impl ReplayRecorder {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            replay: None,
+            cars: HashMap::default(),
+            crashed: Vec::new(),
+            traffic: HashMap::default(),
+            next_traffic_id: 0,
+        }
+    }
+}
+
+/// Replay being played back and the scene currently shown
+#[derive(Resource)]
+pub struct Playback {
+    pub replay: Replay,
+    pub scene: Scene,
+    /// Index of the car followed by the camera
+    pub followed: usize,
+    /// Outcome of the last brain save, shown until the next one
+    pub message: Option<String>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, path: &RoadPath) -> Self {
+        Self {
+            scene: replay.scene_at(0, path),
+            replay,
+            followed: 0,
+            message: None,
+        }
+    }
+
+    pub fn seek(&mut self, tick: usize, path: &RoadPath) {
+        self.scene = self.replay.scene_at(tick, path);
+    }
+}
+
+/// Fitness of the evaluated brain on each finished episode
+#[derive(Resource, Default)]
+pub struct Evaluation {
+    pub episodes: u32,
+    pub fitness: Vec<f32>,
+}
+
 #[derive(Resource)]
 pub struct WindowSize(pub f32, pub f32);
 
-#[derive(Resource, Clone, Copy)]
+/// Configuration file watched for changes while the simulation runs
+#[derive(Resource)]
+pub struct WatchedConfig {
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+    /// Contents of the file when it was last read, telling which values a change touches
+    pub file: ConfigFile,
+    /// How often the modification time of the file is checked
+    pub poll: Timer,
+}
+
+impl WatchedConfig {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        Self {
+            file: ConfigFile::load(&path).unwrap_or_default(),
+            path,
+            modified,
+            poll: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct RoadProperties {
     pub lane_count: u8,
     pub width: f32,
 }
 
 impl RoadProperties {
-    pub fn get_lane_ceter(self, lane_idx: u8) -> f32 {
+    /// Distance from the centreline to the center of a lane, to the right of it
+    pub fn lane_offset(self, lane_idx: u8) -> f32 {
         let lane_width = self.width / f32::from(self.lane_count);
         //(lane_width * lane_idx as f32 - self.width) + lane_width / 2.
         (lane_width * f32::from(lane_idx)) - self.width / 2. + lane_width / 2.
     }
+
+    /// Position of the center of a lane `arc_length` along the road, facing along it
+    pub fn get_lane_ceter(self, path: &RoadPath, lane_idx: u8, arc_length: f32) -> Transform {
+        path.transform_at(arc_length, self.lane_offset(lane_idx))
+    }
+}
+
+/// Colliders sorted along the y axis, the direction the cars drive in. Rebuilt every
+/// fixed step so ray casting and collision checks only test the colliders around a car.
+#[derive(Resource, Default)]
+pub struct BroadPhase {
+    /// Bottom, top and entity of every collider, sorted by their bottom
+    spans: Vec<(f32, f32, Entity)>,
+    /// Tallest span, how far below a range a collider can start and still reach into it
+    max_height: f32,
+    /// Returns every collider from every query, see [`BroadPhase::exhaustive`]
+    exhaustive: bool,
+}
+
+impl BroadPhase {
+    /// Broad phase that doesn't narrow anything down, every car tests every collider like
+    /// before the sweep. Only useful to measure what the sweep saves.
+    pub fn exhaustive() -> Self {
+        Self {
+            exhaustive: true,
+            ..Self::default()
+        }
+    }
+
+    /// Replaces the colliders with the given entities, centers and bounding radiuses
+    pub fn rebuild(&mut self, colliders: impl IntoIterator<Item = (Entity, Vec2, f32)>) {
+        self.spans.clear();
+        self.spans.extend(
+            colliders
+                .into_iter()
+                .map(|(entity, center, radius)| (center.y - radius, center.y + radius, entity)),
+        );
+        self.spans.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        self.max_height = self
+            .spans
+            .iter()
+            .map(|(bottom, top, _)| top - bottom)
+            .fold(0., f32::max);
+    }
+
+    /// Colliders whose span overlaps the range between `min_y` and `max_y`
+    pub fn query(&self, min_y: f32, max_y: f32) -> impl Iterator<Item = Entity> + '_ {
+        let exhaustive = self.exhaustive;
+        let start = if exhaustive {
+            0
+        } else {
+            self.spans
+                .partition_point(|(bottom, _, _)| *bottom < min_y - self.max_height)
+        };
+        self.spans[start..]
+            .iter()
+            .take_while(move |(bottom, _, _)| exhaustive || *bottom <= max_y)
+            .filter(move |(_, top, _)| exhaustive || *top >= min_y)
+            .map(|(_, _, entity)| *entity)
+    }
 }
 
 /// Stores the entity information necessary for the camera transition
@@ -53,3 +524,50 @@ impl CameraTarget {
         self.0 = None;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crashes() -> CrashCounts {
+        CrashCounts {
+            traffic: 3,
+            road_edge: 1,
+            survived: 2,
+        }
+    }
+
+    #[test]
+    fn takes_the_middle_fitness_of_odd_generations() {
+        let record = GenerationRecord::new(4, &[90., 40., 20., 10., 5.], crashes(), "x").unwrap();
+        assert_eq!((record.best, record.median, record.mean), (90., 20., 33.));
+    }
+
+    #[test]
+    fn averages_the_two_middle_fitnesses_of_even_generations() {
+        let record = GenerationRecord::new(4, &[90., 40., 20., 10.], crashes(), "x").unwrap();
+        assert_eq!((record.best, record.median, record.mean), (90., 30., 40.));
+        let record = GenerationRecord::new(4, &[7.], crashes(), "x").unwrap();
+        assert_eq!((record.best, record.median, record.mean), (7., 7., 7.));
+        assert!(GenerationRecord::new(4, &[], crashes(), "x").is_none());
+    }
+
+    #[test]
+    fn writes_one_csv_row_per_generation() {
+        let history = FitnessHistory {
+            records: vec![
+                GenerationRecord::new(0, &[12.5, 2.5], crashes(), "the time limit was reached")
+                    .unwrap(),
+                GenerationRecord::new(1, &[8.], CrashCounts::default(), "every car collided")
+                    .unwrap(),
+            ],
+        };
+        assert_eq!(
+            history.to_csv(),
+            "generation,best,median,mean,traffic_crashes,road_edge_crashes,survived,end_reason\n\
+             0,12.5,7.5,7.5,3,1,2,the time limit was reached\n\
+             1,8,8,8,0,0,0,every car collided\n"
+        );
+        assert_eq!(FitnessHistory::default().to_csv().lines().count(), 1);
+    }
+}
@@ -0,0 +1,349 @@
+//! Centreline of the road, a chain of segments of constant curvature generated from a seed:
+//! straights, gentle curves and S-bends. A position on the road is given by its arc length
+//! from the start line and its offset to the right of the centreline.
+//!
+//! The heading of the road never goes further than [`MAX_HEADING`] from straight up, so the
+//! road always climbs and positions along it stay ordered by their y coordinate.
+
+use crate::resources::RoadProperties;
+use bevy::prelude::{Quat, Resource, Transform, Vec2, Vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::{FRAC_PI_3, PI};
+
+/// Furthest the road turns away from straight up, in radians
+const MAX_HEADING: f32 = FRAC_PI_3;
+/// Straight road the cars start on, also extending behind the start line
+const START_STRAIGHT: f32 = 1500.;
+/// Tightest curve radius, in road widths, when the curviness is 1
+const MIN_RADIUS: f32 = 1.5;
+/// Mixed into the simulation seed so the road doesn't follow the other random streams
+const ROAD_STREAM: u64 = 0x726f_6164_7061_7468;
+
+/// Piece of the centreline, turning to the left when the curvature is positive
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    /// Arc length at which the segment starts
+    start: f32,
+    length: f32,
+    curvature: f32,
+    origin: Vec2,
+    /// Angle from straight up at the origin, counterclockwise
+    heading: f32,
+}
+
+impl Segment {
+    /// Position and heading `t` along the segment
+    fn frame(&self, t: f32) -> (Vec2, f32) {
+        let heading = self.heading + self.curvature * t;
+        if self.curvature == 0. {
+            return (self.origin + direction(self.heading) * t, heading);
+        }
+        let offset = Vec2::new(
+            heading.cos() - self.heading.cos(),
+            heading.sin() - self.heading.sin(),
+        ) / self.curvature;
+        (self.origin + offset, heading)
+    }
+
+    /// Distance along the segment of the point of the segment closest to `point`, within
+    /// the given bounds
+    fn closest(&self, point: Vec2, min: f32, max: f32) -> f32 {
+        let t = if self.curvature == 0. {
+            (point - self.origin).dot(direction(self.heading))
+        } else {
+            // Points of the arc are seen from its center at their heading, or at the
+            // opposite of their heading when turning right
+            let center = self.origin - right(self.heading) / self.curvature;
+            let from_center = (point - center) * self.curvature.signum();
+            let angle = from_center.y.atan2(from_center.x) - self.heading;
+            let turn = (angle + PI).rem_euclid(2. * PI) - PI;
+            turn / self.curvature
+        };
+        t.clamp(min, max)
+    }
+
+    fn end(&self) -> f32 {
+        self.start + self.length
+    }
+}
+
+/// Unit vector pointing forward at the heading
+fn direction(heading: f32) -> Vec2 {
+    Vec2::new(-heading.sin(), heading.cos())
+}
+
+/// Unit vector pointing to the right at the heading
+fn right(heading: f32) -> Vec2 {
+    Vec2::new(heading.cos(), heading.sin())
+}
+
+/// Road centreline, generated further as the cars drive along it
+#[derive(Resource, Clone)]
+pub struct RoadPath {
+    segments: Vec<Segment>,
+    rng: StdRng,
+    /// Curvature of the tightest curves
+    max_curvature: f32,
+    /// Width of the road, how far from the centreline positions are usually projected from
+    width: f32,
+}
+
+impl RoadPath {
+    /// Road generated from `seed`, straight when `curviness` is 0 and with curves as tight as
+    /// one and a half road widths when it's 1
+    pub fn new(seed: u64, road: RoadProperties, curviness: f32) -> Self {
+        Self {
+            segments: vec![Segment {
+                start: 0.,
+                length: START_STRAIGHT,
+                curvature: 0.,
+                origin: Vec2::ZERO,
+                heading: 0.,
+            }],
+            rng: StdRng::seed_from_u64(seed ^ ROAD_STREAM),
+            max_curvature: curviness.clamp(0., 1.) / (road.width * MIN_RADIUS),
+            width: road.width,
+        }
+    }
+
+    /// Arc length up to which the road is generated
+    pub fn length(&self) -> f32 {
+        self.segments.last().map_or(0., Segment::end)
+    }
+
+    /// Generates the road up to `arc_length`, which never changes the road already generated
+    pub fn extend_to(&mut self, arc_length: f32) {
+        while self.length() < arc_length {
+            self.generate();
+        }
+    }
+
+    /// Appends a straight, a curve or an S-bend
+    fn generate(&mut self) {
+        let last = *self.segments.last().unwrap();
+        let (origin, heading) = last.frame(last.length);
+        let kind = if self.max_curvature > 0. {
+            self.rng.gen_range(0..5)
+        } else {
+            0
+        };
+        let pieces: Vec<(f32, f32)> = match kind {
+            // Straight
+            0 | 1 => vec![(self.rng.gen_range(200f32..=700f32), 0.)],
+            // Curve
+            2 | 3 => {
+                let (length, curvature) = self.curve(heading, 0.8);
+                vec![(length, curvature)]
+            }
+            // S-bend, back to the heading it started with
+            _ => {
+                let (length, curvature) = self.curve(heading, 0.6);
+                vec![(length, curvature), (length, -curvature)]
+            }
+        };
+
+        let (mut origin, mut heading, mut start) = (origin, heading, last.end());
+        for (length, curvature) in pieces {
+            let segment = Segment {
+                start,
+                length,
+                curvature,
+                origin,
+                heading,
+            };
+            (origin, heading) = segment.frame(length);
+            start = segment.end();
+            self.segments.push(segment);
+        }
+    }
+
+    /// Length and curvature of a curve turning by up to `max_turn` radians, towards the side
+    /// keeping the road within [`MAX_HEADING`]
+    fn curve(&mut self, heading: f32, max_turn: f32) -> (f32, f32) {
+        let turn = self.rng.gen_range(0.2..=max_turn);
+        let mut curvature = self.rng.gen_range(0.4..=1.) * self.max_curvature;
+        if self.rng.gen_bool(0.5) {
+            curvature = -curvature;
+        }
+        if (heading + turn * curvature.signum()).abs() > MAX_HEADING {
+            curvature = -curvature;
+        }
+        (turn / curvature.abs(), curvature)
+    }
+
+    /// Index of the segment holding `arc_length`, the first and last ones extending beyond
+    /// the generated road
+    fn segment_at(&self, arc_length: f32) -> usize {
+        self.segments
+            .partition_point(|segment| segment.start <= arc_length)
+            .saturating_sub(1)
+    }
+
+    /// Position and heading of the centreline at `arc_length`
+    pub fn frame_at(&self, arc_length: f32) -> (Vec2, f32) {
+        let segment = &self.segments[self.segment_at(arc_length)];
+        segment.frame(arc_length - segment.start)
+    }
+
+    /// Transform of something `offset` to the right of the centreline at `arc_length`,
+    /// facing along the road
+    pub fn transform_at(&self, arc_length: f32, offset: f32) -> Transform {
+        let (position, heading) = self.frame_at(arc_length);
+        Transform {
+            translation: (position + right(heading) * offset).extend(0.),
+            rotation: Quat::from_rotation_z(heading),
+            ..Default::default()
+        }
+    }
+
+    /// Arc length and offset to the right of the centreline of the road position closest
+    /// to `point`
+    pub fn project(&self, point: Vec3) -> (f32, f32) {
+        let point = point.truncate();
+        // The road always climbs, so only the segments starting around the same height can
+        // be close, along with the one reaching that height
+        let starting_below = |y: f32| {
+            self.segments
+                .partition_point(|segment| segment.origin.y <= y)
+        };
+        let first = starting_below(point.y - self.width).saturating_sub(1);
+        let end = starting_below(point.y + self.width).max(first + 1);
+        let last = self.segments.len() - 1;
+        let (mut best, mut best_distance) = ((0., 0.), f32::MAX);
+        for i in first..end {
+            let segment = &self.segments[i];
+            let min = if i == 0 { f32::MIN } else { 0. };
+            let max = if i == last { f32::MAX } else { segment.length };
+            let t = segment.closest(point, min, max);
+            let (position, heading) = segment.frame(t);
+            let distance = point.distance_squared(position);
+            if distance < best_distance {
+                best_distance = distance;
+                best = (segment.start + t, (point - position).dot(right(heading)));
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROAD: RoadProperties = RoadProperties {
+        lane_count: 3,
+        width: 300.,
+    };
+
+    fn curvy_road(seed: u64) -> RoadPath {
+        let mut path = RoadPath::new(seed, ROAD, 1.);
+        path.extend_to(8000.);
+        path
+    }
+
+    /// Checks that positions across the road, all along the segment, project back to where
+    /// they were placed
+    fn assert_projects_back(path: &RoadPath, segment: &Segment) {
+        for step in 0..=10 {
+            let arc_length = segment.start + segment.length * step as f32 / 10.;
+            for offset in [-140., -60., 0., 75., 140.] {
+                let position = path.transform_at(arc_length, offset).translation;
+                let (projected, projected_offset) = path.project(position);
+                assert!(
+                    (projected - arc_length).abs() < 0.05
+                        && (projected_offset - offset).abs() < 0.05,
+                    "{segment:?}: ({arc_length}, {offset}) projected to \
+                     ({projected}, {projected_offset})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn projects_positions_back_on_every_kind_of_segment() {
+        let (mut straights, mut left, mut right, mut s_bends) = (0, 0, 0, 0);
+        for seed in 0..5 {
+            let path = curvy_road(seed);
+            for segment in &path.segments {
+                match segment.curvature {
+                    c if c > 0. => left += 1,
+                    c if c < 0. => right += 1,
+                    _ => straights += 1,
+                }
+                assert_projects_back(&path, segment);
+            }
+            // Both halves of the S-bends are checked above, where they meet too
+            s_bends += path
+                .segments
+                .windows(2)
+                .filter(|pair| pair[0].curvature != 0. && pair[0].curvature == -pair[1].curvature)
+                .count();
+        }
+        assert!(straights > 0 && left > 0 && right > 0 && s_bends > 0);
+
+        // Behind the start line the first straight goes on
+        let path = curvy_road(0);
+        let (behind, offset) = path.project(path.transform_at(-200., 30.).translation);
+        assert!((behind + 200.).abs() < 0.01 && (offset - 30.).abs() < 0.01);
+    }
+
+    #[test]
+    fn generates_the_same_road_however_it_is_extended() {
+        let at_once = curvy_road(7);
+        let mut bit_by_bit = RoadPath::new(7, ROAD, 1.);
+        let mut arc_length: f32 = 0.;
+        while arc_length < 8000. {
+            arc_length += 37.;
+            bit_by_bit.extend_to(arc_length.min(8000.));
+        }
+        assert_eq!(at_once.segments.len(), bit_by_bit.segments.len());
+        let frames = |path: &RoadPath| -> Vec<(Vec2, f32)> {
+            (0..800).map(|i| path.frame_at(i as f32 * 10.)).collect()
+        };
+        assert_eq!(frames(&at_once), frames(&bit_by_bit));
+
+        // Extending further keeps the road already generated
+        let mut extended = at_once.clone();
+        extended.extend_to(20_000.);
+        assert_eq!(frames(&at_once), frames(&extended));
+        assert_ne!(
+            frames(&curvy_road(8)),
+            frames(&at_once),
+            "another seed should give another road"
+        );
+    }
+
+    #[test]
+    fn keeps_the_heading_within_the_limit() {
+        for seed in 0..20 {
+            let path = curvy_road(seed);
+            for segment in &path.segments {
+                let (_, end_heading) = segment.frame(segment.length);
+                for heading in [segment.heading, end_heading] {
+                    assert!(
+                        heading.abs() <= MAX_HEADING + 1e-4,
+                        "seed {seed}: heading {heading} in {segment:?}"
+                    );
+                }
+            }
+            // So the road keeps climbing
+            let heights: Vec<f32> = (0..800)
+                .map(|i| path.frame_at(i as f32 * 10.).0.y)
+                .collect();
+            assert!(
+                heights.windows(2).all(|pair| pair[0] < pair[1]),
+                "seed {seed}"
+            );
+        }
+    }
+
+    #[test]
+    fn stays_straight_without_curviness() {
+        let mut path = RoadPath::new(3, ROAD, 0.);
+        path.extend_to(5000.);
+        assert!(path.segments.iter().all(|segment| segment.curvature == 0.));
+        let (position, heading) = path.frame_at(4321.);
+        assert_eq!((position, heading), (Vec2::new(0., 4321.), 0.));
+    }
+}
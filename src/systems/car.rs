@@ -1,60 +1,68 @@
+use crate::brain::{read_brain, BRAINS_DIR};
 use crate::components::{
-    CameraFollowMarker, Car, CarCollided, CarsArray, ControllableCarBundle, Controls,
-    NeuralNetwork, RayBundle, TrafficArray, TrafficCarBundle,
+    BodySize, CameraFollowMarker, Car, CarCollided, CarsArray, ControllableCarBundle, Controls,
+    CrashCause, FitnessScore, HumanDriver, NeuralNetwork, RayBundle, TrafficArray, TrafficCar,
+    TrafficCarBundle,
 };
-use crate::resources::{CameraTarget, Config, NetworkConfig, RoadProperties, WindowSize};
-use crate::utils::lerp;
+use crate::resources::{
+    BroadPhase, CameraTarget, Config, NetworkConfig, RoadProperties, SimulationRng, StartupBrain,
+    WindowSize,
+};
+use crate::road::RoadPath;
+use crate::utils::{bounding_radius, rect_corners, rects_overlap};
 use crate::{query_filters, AppState, ChangeTargetEvent, LoadNetworkEvent};
+use bevy::app::AppExit;
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb;
 use rand::Rng;
-use std::f32::consts::PI;
+
+pub(super) const HUMAN_CAR_COLOR: Color = Color::ORANGE;
+
+type MovingCars<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static mut Car,
+        &'static mut Transform,
+        Option<&'static Controls>,
+        Option<&'static mut TrafficCar>,
+    ),
+    Without<CarCollided>,
+>;
 
 pub fn setup(
     mut commands: Commands,
     window_size: Res<WindowSize>,
     road: Res<RoadProperties>,
+    path: Res<RoadPath>,
     mut config: ResMut<Config>,
     network_config: Res<NetworkConfig>,
+    mut rng: ResMut<SimulationRng>,
 ) {
     commands
         .spawn_empty()
         .insert(SpatialBundle::default())
         .insert(CarsArray)
         .with_children(|parent| {
+            let network_layers = network_config.layers();
             (0..config.controlllable_cars).for_each(|_| {
-                let mut ray_ids: Vec<Entity> = vec![];
-                let mut car = parent.spawn(ControllableCarBundle::new(Vec2 {
-                    x: road.get_lane_ceter(2),
-                    y: -window_size.1 / 4.,
-                }));
-                car.with_children(|parent| {
-                    (0..network_config.input_neuron_count).for_each(|i| {
-                        let ray_angle = {
-                            let t = if network_config.input_neuron_count == 1 {
-                                0.5
-                            } else {
-                                f32::from(i) / f32::from(network_config.input_neuron_count - 1)
-                            };
-                            let a = network_config.input_ray_spread / 2.;
-                            lerp::<f32, f32>(a, -a, t)
-                        };
-                        ray_ids.push(
-                            parent
-                                .spawn(RayBundle::new(network_config.input_ray_length, ray_angle))
-                                .remove::<Visibility>()
-                                .id(),
-                        );
-                    });
-                });
-                let mut network_layers: Vec<u8> = Vec::new();
-                network_layers.insert(0, network_config.input_neuron_count);
-                (1..network_config.hidden_layers).for_each(|idx| {
-                    network_layers.insert(idx.into(), network_config.hidden_layers_neuron_count);
-                });
-                network_layers.push(network_config.output_neuron_count);
-
-                car.insert(NeuralNetwork::new(&network_layers, ray_ids));
+                spawn_controllable_car(
+                    parent,
+                    &window_size,
+                    &road,
+                    &path,
+                    &network_config,
+                    |ray_ids| {
+                        NeuralNetwork::new(
+                            &network_layers,
+                            ray_ids,
+                            (
+                                network_config.hidden_activation,
+                                network_config.output_activation,
+                            ),
+                            &mut rng.evolution,
+                        )
+                    },
+                );
             });
         });
 
@@ -63,127 +71,169 @@ pub fn setup(
         .insert(SpatialBundle::default())
         .insert(TrafficArray)
         .with_children(|parent| {
-            // Initial traffic - spawn one third of the max traffic
-            (0..config.max_traffic / 3).for_each(|i| {
-                let random_lane: u8 = rand::thread_rng().gen_range(0..road.lane_count);
-                let random_y: f32 =
-                    rand::thread_rng().gen_range(0f32..=(f32::from(i) * 100f32)) + 100f32;
-                parent.spawn(TrafficCarBundle::new(
-                    road.get_lane_ceter(random_lane),
-                    random_y,
-                ));
-                config.current_traffic += 1;
-            });
+            spawn_initial_traffic(parent, &road, &path, &mut config, &mut rng.traffic)
         });
 
     commands.spawn(Camera2dBundle::default());
 }
 
-pub fn move_cars(
-    mut car_q: Query<(&mut Car, &mut Transform, Option<&Controls>), Without<CarCollided>>,
-    time: Res<FixedTime>,
+/// Spawns a controllable car at the start position along with its rays,
+/// `new_network` receives the rays ids to build the car's brain
+pub(super) fn spawn_controllable_car<B: Bundle>(
+    parent: &mut ChildBuilder,
+    window_size: &WindowSize,
+    road: &RoadProperties,
+    path: &RoadPath,
+    network_config: &NetworkConfig,
+    new_driver: impl FnOnce(Vec<Entity>) -> B,
 ) {
-    car_q.for_each_mut(|(mut car, mut car_xform, car_controls)| {
-        let mut rotation_factor = 0.;
-        if let Some(control) = car_controls {
-            car.speed += car.acceleration * control.acceleration;
-        } else {
-            car.speed += car.acceleration;
-        }
+    let mut ray_ids: Vec<Entity> = vec![];
+    let start_progress = -window_size.1 / 4.;
+    let mut car = parent.spawn(ControllableCarBundle::new(
+        road.get_lane_ceter(path, 2.min(road.lane_count - 1), start_progress),
+        start_progress,
+    ));
+    car.with_children(|parent| {
+        network_config
+            .ray_angles()
+            .into_iter()
+            .for_each(|ray_angle| {
+                ray_ids.push(
+                    parent
+                        .spawn(RayBundle::new(network_config.input_ray_length, ray_angle))
+                        .remove::<Visibility>()
+                        .id(),
+                );
+            });
+    });
+    car.insert(new_driver(ray_ids));
+}
 
-        car.speed = match car.speed {
-            speed if speed.abs() < car.friction => 0.,
-            speed if speed < 0. => speed + car.friction,
-            speed if speed > 0. => speed - car.friction,
-            _ => 0.,
-        };
-        car.speed = car.speed.clamp(-car.max_speed * 0.5, car.max_speed);
+fn spawn_initial_traffic(
+    parent: &mut ChildBuilder,
+    road: &RoadProperties,
+    path: &RoadPath,
+    config: &mut Config,
+    rng: &mut impl Rng,
+) {
+    // Initial traffic - spawn one third of the max traffic
+    (0..config.max_traffic / 3).for_each(|i| {
+        let random_lane: u8 = rng.gen_range(0..road.lane_count);
+        let random_progress: f32 = rng.gen_range(0f32..=(f32::from(i) * 100f32)) + 100f32;
+        parent.spawn(TrafficCarBundle::new(
+            TrafficCar {
+                lane: random_lane,
+                progress: random_progress,
+            },
+            road.get_lane_ceter(path, random_lane, random_progress),
+            rng,
+        ));
+        config.current_traffic += 1;
+    });
+}
 
-        if car.speed != 0. && car.speed.abs() > car.acceleration * 1.5 {
-            car.handling =
-                f32::to_radians(car.max_handling / (car.speed / 100.)).clamp(-PI * 0.66, PI * 0.66);
-            if let Some(control) = car_controls {
-                rotation_factor = control.turn_direction;
+/// Drives the controllable cars as their controls tell and the traffic along its lanes
+pub fn move_cars(
+    mut car_q: MovingCars,
+    road: Res<RoadProperties>,
+    path: Res<RoadPath>,
+    time: Res<FixedTime>,
+) {
+    let delta = time.period.as_secs_f32();
+    car_q.for_each_mut(
+        |(mut car, mut car_xform, car_controls, traffic_car)| match traffic_car {
+            Some(mut traffic_car) => {
+                traffic_car.drive(&mut car, &mut car_xform, *road, &path, delta)
             }
-        }
-        car_xform.rotate_z(rotation_factor * car.handling * time.period.as_secs_f32());
-
-        let movement_delta =
-            (car_xform.rotation * Vec3::Y) * (car.speed * time.period.as_secs_f32());
-        car_xform.translation += movement_delta;
-    });
+            None => car.drive(&mut car_xform, car_controls, delta),
+        },
+    );
 }
 
 pub fn check_collisions(
     mut commands: Commands,
-    mut cars_q: Query<(&Transform, &Children, &mut Sprite, Entity), query_filters::ControllableCar>,
-    colliders_q: Query<(&Transform, &Sprite), query_filters::Collider>,
+    mut cars_q: Query<
+        (&Transform, &BodySize, &Children, &mut Sprite, Entity),
+        query_filters::ControllableCar,
+    >,
+    colliders_q: Query<(&Transform, &BodySize, Option<&TrafficCar>), query_filters::Collider>,
+    broad_phase: Res<BroadPhase>,
 ) {
-    for (car_xform, car_children, mut car_sprite, car_id) in &mut cars_q {
-        for (collider_xform, collider_sprite) in colliders_q.iter() {
+    for (car_xform, car_size, car_children, mut car_sprite, car_id) in &mut cars_q {
+        let car_corners = rect_corners(car_xform, car_size.0);
+        let car_radius = bounding_radius(car_size.0);
+        let car_y = car_xform.translation.y;
+        for candidate in broad_phase.query(car_y - car_radius, car_y + car_radius) {
+            let Ok((collider_xform, collider_size, traffic)) = colliders_q.get(candidate) else {
+                continue;
+            };
             if car_xform.translation.distance(collider_xform.translation)
-                >= car_sprite.custom_size.unwrap().y
+                >= car_radius + bounding_radius(collider_size.0)
             {
                 continue;
             }
-            if let Some(collider_size) = collider_sprite.custom_size {
-                if collide_aabb::collide(
-                    car_xform.translation,
-                    car_sprite.custom_size.unwrap(),
-                    collider_xform.translation,
-                    collider_size,
-                )
-                .is_some()
-                {
-                    let mut car_entity = commands.entity(car_id);
-                    car_entity.remove_children(car_children);
-                    car_entity.insert(CarCollided);
-                    car_entity.remove::<CameraFollowMarker>();
-                    for child in car_children {
-                        commands.entity(*child).despawn();
-                    }
-                    car_sprite.color.set_a(50.);
-                    car_sprite.color = Color::DARK_GRAY;
+            if rects_overlap(&car_corners, &rect_corners(collider_xform, collider_size.0)) {
+                let mut car_entity = commands.entity(car_id);
+                car_entity.remove_children(car_children);
+                car_entity.insert(CarCollided(if traffic.is_some() {
+                    CrashCause::Traffic
+                } else {
+                    CrashCause::RoadEdge
+                }));
+                car_entity.remove::<CameraFollowMarker>();
+                for child in car_children {
+                    commands.entity(*child).despawn();
                 }
+                car_sprite.color.set_a(50.);
+                car_sprite.color = Color::DARK_GRAY;
+                // The rays are already gone, a second collision would despawn them again
+                break;
             }
         }
     }
 }
 
 pub fn find_new_camera_target(
-    cars_q: Query<(&Transform, Entity, &Children), query_filters::ControllableCar>,
+    cars_q: Query<(&FitnessScore, Entity), query_filters::ControllableCar>,
+    human_q: Query<Entity, query_filters::HumanCar>,
     mut camera_target: ResMut<CameraTarget>,
     mut ev_change_target: EventWriter<ChangeTargetEvent>,
 ) {
-    let mut furthermost_y_car: Option<Entity> = camera_target.get_target();
-    let mut furthermost_y_value: f32 = match furthermost_y_car {
+    // The human car is followed for as long as it drives
+    if let Some(human) = human_q.iter().next() {
+        let current_target = camera_target.get_target();
+        if current_target != Some(human) {
+            ev_change_target.send(ChangeTargetEvent(human, current_target));
+        }
+        return;
+    }
+
+    let mut fittest_car: Option<Entity> = camera_target.get_target();
+    let mut fittest_value: f32 = match fittest_car {
         Some(car_id) => {
-            if let Ok((x_form, _, _)) = cars_q.get(car_id) {
-                x_form.translation.y
+            if let Ok((fitness, _)) = cars_q.get(car_id) {
+                fitness.0
             } else {
-                -1000.0
+                f32::MIN
             }
         }
-        None => -1000.0,
+        None => f32::MIN,
     };
-    for (car_xform, car_id, _) in cars_q.iter() {
-        if furthermost_y_car.is_none() {
+    for (fitness, car_id) in cars_q.iter() {
+        if fittest_car.is_none() {
             // There's no target, make the first available as the new target
             ev_change_target.send(ChangeTargetEvent(car_id, None));
             return;
         }
-        if car_xform.translation.y > furthermost_y_value {
-            furthermost_y_value = car_xform.translation.y;
-            furthermost_y_car = Some(car_id);
+        if fitness.0 > fittest_value {
+            fittest_value = fitness.0;
+            fittest_car = Some(car_id);
         }
     }
 
     if let Some(curr_target) = camera_target.get_target() {
-        if furthermost_y_car.unwrap() != curr_target {
-            ev_change_target.send(ChangeTargetEvent(
-                furthermost_y_car.unwrap(),
-                Some(curr_target),
-            ));
+        if fittest_car.unwrap() != curr_target {
+            ev_change_target.send(ChangeTargetEvent(fittest_car.unwrap(), Some(curr_target)));
         }
     } else {
         camera_target.remove_target();
@@ -192,7 +242,10 @@ pub fn find_new_camera_target(
 
 pub fn update_camera_target(
     mut commands: Commands,
-    mut cars_q: Query<(&mut Sprite, &Children), query_filters::ControllableCar>,
+    mut cars_q: Query<
+        (&mut Sprite, &Children, Option<&HumanDriver>),
+        query_filters::ControllableCar,
+    >,
     mut camera_target: ResMut<CameraTarget>,
     mut ev_change_target: EventReader<ChangeTargetEvent>,
 ) {
@@ -201,7 +254,7 @@ pub fn update_camera_target(
         if let Some(prev_target) = change_target.1 {
             'disable_prev_target: {
                 if change_target.0 != prev_target {
-                    let Ok((mut prev_target_sprite, prev_target_children)) =
+                    let Ok((mut prev_target_sprite, prev_target_children, _)) =
                         cars_q.get_mut(prev_target)
                     else {
                         break 'disable_prev_target;
@@ -215,12 +268,17 @@ pub fn update_camera_target(
                 }
             }
         }
-        let Ok((mut target_sprite, target_children)) = cars_q.get_mut(change_target.0) else {
+        let Ok((mut target_sprite, target_children, human)) = cars_q.get_mut(change_target.0)
+        else {
             return;
         };
         camera_target.set_target(change_target.0);
         commands.entity(change_target.0).insert(CameraFollowMarker);
-        target_sprite.color = Color::YELLOW_GREEN;
+        target_sprite.color = if human.is_some() {
+            HUMAN_CAR_COLOR
+        } else {
+            Color::YELLOW_GREEN
+        };
         target_sprite.color.set_a(1.);
         for child in target_children {
             commands.entity(*child).insert(Visibility::Visible);
@@ -231,45 +289,53 @@ pub fn update_camera_target(
 pub fn despawn_traffic(
     mut commands: Commands,
     camera_q: Query<&Transform, With<Camera2d>>,
-    traffic_q: Query<(Entity, &Transform), query_filters::Traffic>,
+    traffic_q: Query<(Entity, &TrafficCar), query_filters::Traffic>,
     window_size: Res<WindowSize>,
+    path: Res<RoadPath>,
     mut options: ResMut<Config>,
 ) {
-    let camera_xform = camera_q.single();
-    let y_position_constraints = (
-        camera_xform.translation.y - window_size.1,
-        camera_xform.translation.y + window_size.1 * 2.,
+    let (camera_progress, _) = path.project(camera_q.single().translation);
+    let progress_constraints = (
+        camera_progress - window_size.1,
+        camera_progress + window_size.1 * 2.,
     );
 
-    for (traffic_car_id, traffic_car_xform) in traffic_q.iter() {
-        if !(y_position_constraints.0..=y_position_constraints.1)
-            .contains(&traffic_car_xform.translation.y)
-        {
+    for (traffic_car_id, traffic_car) in traffic_q.iter() {
+        if !(progress_constraints.0..=progress_constraints.1).contains(&traffic_car.progress) {
             commands.entity(traffic_car_id).despawn();
             options.current_traffic -= 1;
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_traffic(
     mut commands: Commands,
     traffic_array_q: Query<Entity, With<TrafficArray>>,
     camera_q: Query<&Transform, With<Camera2d>>,
     window_size: Res<WindowSize>,
     road: Res<RoadProperties>,
+    mut path: ResMut<RoadPath>,
     mut options: ResMut<Config>,
+    mut rng: ResMut<SimulationRng>,
 ) {
-    let camera_xform = camera_q.single();
-    let min_y = camera_xform.translation.y + window_size.1;
+    let (camera_progress, _) = path.project(camera_q.single().translation);
+    let min_progress = camera_progress + window_size.1;
     let traffic_array = traffic_array_q.single();
 
-    (0..options.max_traffic - options.current_traffic).for_each(|i| {
-        let random_lane: u8 = rand::thread_rng().gen_range(0..road.lane_count);
-        let random_y: f32 = rand::thread_rng().gen_range(0f32..=(f32::from(i) * 100f32)) + min_y;
+    (0..options.max_traffic.saturating_sub(options.current_traffic)).for_each(|i| {
+        let random_lane: u8 = rng.traffic.gen_range(0..road.lane_count);
+        let random_progress: f32 =
+            rng.traffic.gen_range(0f32..=(f32::from(i) * 100f32)) + min_progress;
+        path.extend_to(random_progress);
         let new_car = commands
             .spawn(TrafficCarBundle::new(
-                road.get_lane_ceter(random_lane),
-                random_y,
+                TrafficCar {
+                    lane: random_lane,
+                    progress: random_progress,
+                },
+                road.get_lane_ceter(&path, random_lane, random_progress),
+                &mut rng.traffic,
             ))
             .id();
         commands.entity(traffic_array).add_child(new_car);
@@ -283,20 +349,22 @@ pub fn load_network(
     cars_array_q: Query<(Entity, &Children), With<CarsArray>>,
     window_size: Res<WindowSize>,
     road: Res<RoadProperties>,
+    path: Res<RoadPath>,
     config: Res<Config>,
     mut network_config: ResMut<NetworkConfig>,
+    mut rng: ResMut<SimulationRng>,
     mut ev_load_network: EventReader<LoadNetworkEvent>,
 ) {
-    let Some(network_levels) = ev_load_network.iter().next().map(|n| &n.0) else {
+    let Some(brain) = ev_load_network.iter().next().map(|n| &n.0) else {
         commands.insert_resource(State::new(AppState::Running));
         return;
     };
-    // Update network_config to match the loaded network
-    network_config.input_neuron_count = network_levels[0].inputs.len() as u8;
-    network_config.hidden_layers = network_levels.len() as u8;
-    network_config.hidden_layers_neuron_count = network_levels[0].outputs.len() as u8;
-    network_config.output_neuron_count =
-        network_levels[network_levels.len() - 1].outputs.len() as u8;
+    let network_levels = &brain.levels;
+    // Take the shape and sensors the network was saved with, but keep mutating as configured
+    *network_config = NetworkConfig {
+        mutate_factor: network_config.mutate_factor,
+        ..brain.metadata.network.clone()
+    };
 
     // Despawn all controllabled cars
     let (cars_array_id, children) = cars_array_q.single();
@@ -309,38 +377,61 @@ pub fn load_network(
     // Respawn cars with new network
     commands.entity(cars_array_id).with_children(|parent| {
         (0..config.controlllable_cars).for_each(|_| {
-            let mut ray_ids: Vec<Entity> = vec![];
-            let mut car = parent.spawn(ControllableCarBundle::new(Vec2 {
-                x: road.get_lane_ceter(2),
-                y: -window_size.1 / 4.,
-            }));
-            car.with_children(|parent| {
-                (0..network_config.input_neuron_count).for_each(|i| {
-                    let ray_angle = {
-                        let t = if network_config.input_neuron_count == 1 {
-                            0.5
-                        } else {
-                            f32::from(i) / f32::from(network_config.input_neuron_count - 1)
-                        };
-                        let a = network_config.input_ray_spread / 2.;
-                        lerp::<f32, f32>(a, -a, t)
-                    };
-                    ray_ids.push(
-                        parent
-                            .spawn(RayBundle::new(network_config.input_ray_length, ray_angle))
-                            .remove::<Visibility>()
-                            .id(),
-                    );
-                });
-            });
-
-            car.insert(NeuralNetwork::with_levels(
-                network_levels.to_vec(),
-                ray_ids,
-                network_config.mutate_factor,
-            ));
+            spawn_controllable_car(
+                parent,
+                &window_size,
+                &road,
+                &path,
+                &network_config,
+                |ray_ids| {
+                    NeuralNetwork::with_levels(
+                        network_levels.to_vec(),
+                        ray_ids,
+                        network_config.mutate_factor,
+                        &mut rng.evolution,
+                    )
+                },
+            );
         });
     });
 
     commands.insert_resource(State::new(AppState::Running));
 }
+
+/// Loads the brain given at startup into the whole population, exiting when it can't be read
+pub fn load_startup_brain(
+    mut commands: Commands,
+    startup_brain: Res<StartupBrain>,
+    mut ev_load_network: EventWriter<LoadNetworkEvent>,
+    mut ev_exit: EventWriter<AppExit>,
+) {
+    let brain = match startup_brain.0.resolve(BRAINS_DIR).and_then(read_brain) {
+        Ok(brain) => brain,
+        Err(e) => {
+            error!("Couldn't load the brain {}: {e}", startup_brain.0);
+            ev_exit.send(AppExit);
+            return;
+        }
+    };
+    commands.insert_resource(State::new(AppState::LoadingNetwork));
+    ev_load_network.send(LoadNetworkEvent(brain));
+}
+
+/// Replaces the current traffic with a fresh initial traffic
+pub fn reset_traffic(
+    mut commands: Commands,
+    traffic_array_q: Query<Entity, With<TrafficArray>>,
+    road: Res<RoadProperties>,
+    path: Res<RoadPath>,
+    mut config: ResMut<Config>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let traffic_array = traffic_array_q.single();
+    config.current_traffic = 0;
+    commands
+        .entity(traffic_array)
+        .despawn_descendants()
+        .with_children(|parent| {
+            spawn_initial_traffic(parent, &road, &path, &mut config, &mut rng.traffic)
+        });
+}
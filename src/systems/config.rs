@@ -0,0 +1,70 @@
+use crate::components::Ray;
+use crate::config_file::ConfigFile;
+use crate::resources::{Config, FitnessFunction, NetworkConfig, WatchedConfig};
+use bevy::prelude::*;
+
+/// Applies the values that don't change the structure of the simulation when the
+/// configuration file is modified. Population, network shape and road changes are
+/// ignored until the simulation is restarted. Only the values changed in the file are
+/// applied, so command line options keep overriding the ones left untouched.
+pub fn hot_reload(
+    mut watched: ResMut<WatchedConfig>,
+    time: Res<Time>,
+    mut config: ResMut<Config>,
+    mut network_config: ResMut<NetworkConfig>,
+    mut fitness: ResMut<FitnessFunction>,
+    mut rays_q: Query<&mut Ray>,
+) {
+    if !watched.poll.tick(time.raw_delta()).just_finished() {
+        return;
+    }
+    let modified = std::fs::metadata(&watched.path)
+        .and_then(|m| m.modified())
+        .ok();
+    if modified.is_none() || modified == watched.modified {
+        return;
+    }
+    watched.modified = modified;
+
+    let file = match ConfigFile::load(&watched.path) {
+        Ok(file) => file,
+        Err(e) => {
+            error!("Ignoring {}: {e}", watched.path.display());
+            return;
+        }
+    };
+
+    let previous = std::mem::replace(&mut watched.file, file.clone());
+    let (simulation, previous_simulation) = (file.simulation, previous.simulation);
+    if simulation.max_traffic != previous_simulation.max_traffic {
+        config.max_traffic = simulation.max_traffic;
+    }
+    if simulation.survivors != previous_simulation.survivors {
+        config.survivors = simulation.survivors.min(config.controlllable_cars);
+    }
+    if simulation.max_generation_duration != previous_simulation.max_generation_duration {
+        config.max_generation_duration = simulation.max_generation_duration;
+    }
+    if simulation.stagnation_timeout != previous_simulation.stagnation_timeout {
+        config.stagnation_timeout = simulation.stagnation_timeout;
+    }
+    if simulation.crossover != previous_simulation.crossover {
+        config.crossover = simulation.crossover;
+    }
+    if simulation.fitness != previous_simulation.fitness {
+        config.fitness = simulation.fitness;
+        *fitness = FitnessFunction::from_config(&config);
+    }
+
+    let (network, previous_network) = (file.network, previous.network);
+    if network.mutate_factor != previous_network.mutate_factor {
+        network_config.mutate_factor = network.mutate_factor;
+    }
+    if network.input_ray_length != previous_network.input_ray_length {
+        network_config.input_ray_length = network.input_ray_length;
+        for mut ray in &mut rays_q {
+            ray.length = network_config.input_ray_length;
+        }
+    }
+    info!("Reloaded {}", watched.path.display());
+}
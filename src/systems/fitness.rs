@@ -0,0 +1,46 @@
+use crate::components::{CarStats, Controls, FitnessScore, TrafficCar};
+use crate::query_filters;
+use crate::resources::{FitnessFunction, RoadProperties};
+use crate::road::RoadPath;
+use bevy::prelude::{Entity, FixedTime, Query, Res, Transform};
+
+/// Updates the driving statistics of every car still on the road and scores them
+pub fn evaluate(
+    mut cars_q: Query<
+        (&Transform, &Controls, &mut CarStats, &mut FitnessScore),
+        query_filters::ControllableCar,
+    >,
+    traffic_q: Query<(&TrafficCar, Entity), query_filters::Traffic>,
+    road: Res<RoadProperties>,
+    path: Res<RoadPath>,
+    fitness: Res<FitnessFunction>,
+    time: Res<FixedTime>,
+) {
+    let lane_width = road.width / f32::from(road.lane_count);
+    for (car_xform, controls, mut stats, mut score) in &mut cars_q {
+        stats.ticks += 1;
+        stats.time_alive += time.period.as_secs_f32();
+        let (progress, offset) = path.project(car_xform.translation);
+        stats.distance = progress - stats.start_progress;
+
+        for (traffic_car, traffic_id) in traffic_q.iter() {
+            if traffic_car.progress < progress {
+                stats.overtaken.insert(traffic_id);
+            } else {
+                stats.overtaken.remove(&traffic_id);
+            }
+        }
+
+        let lane_offset = (0..road.lane_count)
+            .map(|lane| (offset - road.lane_offset(lane)).abs())
+            .fold(f32::MAX, f32::min);
+        stats.lane_keeping += (1. - lane_offset / (lane_width / 2.)).max(0.);
+
+        let controls_change = (controls.acceleration - stats.last_controls.0).abs()
+            + (controls.turn_direction - stats.last_controls.1).abs();
+        stats.smoothness += 1. - (controls_change / 4.).min(1.);
+        stats.last_controls = (controls.acceleration, controls.turn_direction);
+
+        score.0 = fitness.0.score(&stats);
+    }
+}
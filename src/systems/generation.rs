@@ -0,0 +1,231 @@
+use super::car::spawn_controllable_car;
+use crate::components::{
+    Car, CarCollided, CarStats, CarsArray, CrashCause, Crossover, FitnessScore, NetworkLevel,
+    NeuralNetwork,
+};
+use crate::events::{GenerationEndedEvent, NextGenerationEvent};
+use crate::resources::{
+    CameraTarget, Config, CrashCounts, Evaluation, Generation, NetworkConfig, RoadProperties,
+    SimulationRng, WindowSize,
+};
+use crate::road::RoadPath;
+use crate::{query_filters, AppState};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Minimum distance the leading car has to advance to not be considered stagnant
+const PROGRESS_MARGIN: f32 = 1.0;
+
+/// Ends the generation once every car has crashed, the leading car stopped making
+/// progress or the generation ran for too long, selecting the fittest cars as survivors
+#[allow(clippy::too_many_arguments)]
+pub fn check_generation_end(
+    mut commands: Commands,
+    cars_q: Query<
+        (
+            &CarStats,
+            &NeuralNetwork,
+            &FitnessScore,
+            Option<&CarCollided>,
+        ),
+        With<Car>,
+    >,
+    human_q: Query<(), query_filters::HumanCar>,
+    config: Res<Config>,
+    mut generation: ResMut<Generation>,
+    time: Res<FixedTime>,
+    rng: Res<SimulationRng>,
+    mut ev_next_generation: EventWriter<NextGenerationEvent>,
+    mut ev_generation_ended: EventWriter<GenerationEndedEvent>,
+) {
+    if cars_q.is_empty() {
+        return;
+    }
+    let delta = time.period.as_secs_f32();
+    generation.elapsed += delta;
+
+    let mut crashes = CrashCounts::default();
+    let mut leader_distance = f32::MIN;
+    for (stats, _, _, collided) in cars_q.iter() {
+        match collided {
+            Some(CarCollided(CrashCause::Traffic)) => crashes.traffic += 1,
+            Some(CarCollided(CrashCause::RoadEdge)) => crashes.road_edge += 1,
+            None => {
+                crashes.survived += 1;
+                leader_distance = leader_distance.max(stats.distance);
+            }
+        }
+    }
+
+    if leader_distance > generation.best_progress + PROGRESS_MARGIN {
+        generation.best_progress = leader_distance;
+        generation.stagnant_for = 0.;
+    } else {
+        generation.stagnant_for += delta;
+    }
+
+    // Someone driving shouldn't be interrupted before the time limit
+    let human_driving = !human_q.is_empty();
+    let end_reason = if crashes.survived == 0 && !human_driving {
+        "every car collided"
+    } else if generation.stagnant_for >= config.stagnation_timeout && !human_driving {
+        "no progress was made"
+    } else if generation.elapsed >= config.max_generation_duration {
+        "the time limit was reached"
+    } else {
+        return;
+    };
+
+    let mut ranked_cars: Vec<(&NeuralNetwork, f32)> = cars_q
+        .iter()
+        .map(|(_, brain, fitness, _)| (brain, fitness.0))
+        .collect();
+    ranked_cars.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let survivors: Vec<_> = ranked_cars
+        .iter()
+        .take(usize::from(config.survivors.max(1)))
+        .map(|(brain, _)| brain.levels.clone())
+        .collect();
+
+    info!(
+        "Generation {} (seed {}) ended after {:.1}s because {}, best fitness: {:.1}",
+        generation.count,
+        rng.seed(),
+        generation.elapsed,
+        end_reason,
+        ranked_cars[0].1
+    );
+    ev_generation_ended.send(GenerationEndedEvent {
+        generation: generation.count,
+        fitness: ranked_cars.iter().map(|(_, fitness)| *fitness).collect(),
+        crashes,
+        end_reason,
+    });
+    ev_next_generation.send(NextGenerationEvent(survivors));
+    commands.insert_resource(State::new(AppState::NextGeneration));
+}
+
+/// Replaces the population with the offspring of the previous generation survivors.
+/// The best survivor is carried over unchanged, every other car is a mutated child of two
+/// random survivors, or a mutated copy of a survivor when crossover is disabled.
+#[allow(clippy::too_many_arguments)]
+pub fn next_generation(
+    mut commands: Commands,
+    cars_array_q: Query<Entity, With<CarsArray>>,
+    window_size: Res<WindowSize>,
+    road: Res<RoadProperties>,
+    path: Res<RoadPath>,
+    config: Res<Config>,
+    network_config: Res<NetworkConfig>,
+    mut generation: ResMut<Generation>,
+    mut camera_target: ResMut<CameraTarget>,
+    mut rng: ResMut<SimulationRng>,
+    mut ev_next_generation: EventReader<NextGenerationEvent>,
+) {
+    let Some(survivors) = ev_next_generation.iter().next().map(|e| &e.0) else {
+        commands.insert_resource(State::new(AppState::Running));
+        return;
+    };
+
+    let rng = &mut *rng;
+    let cars_array = cars_array_q.single();
+    commands
+        .entity(cars_array)
+        .despawn_descendants()
+        .with_children(|parent| {
+            (0..usize::from(config.controlllable_cars)).for_each(|i| {
+                let (levels, mutate_factor) = if i == 0 {
+                    (survivors[0].clone(), 0.)
+                } else {
+                    (
+                        breed(survivors, i, config.crossover, &mut rng.evolution),
+                        network_config.mutate_factor,
+                    )
+                };
+                spawn_controllable_car(
+                    parent,
+                    &window_size,
+                    &road,
+                    &path,
+                    &network_config,
+                    |ray_ids| {
+                        NeuralNetwork::with_levels(
+                            levels,
+                            ray_ids,
+                            mutate_factor,
+                            &mut rng.evolution,
+                        )
+                    },
+                );
+            });
+        });
+
+    camera_target.remove_target();
+    generation.advance();
+    rng.reset_traffic(generation.count);
+    commands.insert_resource(State::new(AppState::Running));
+}
+
+/// Records the fitness of the evaluated brain on every episode, exiting once all are done
+pub fn record_evaluation(
+    mut evaluation: ResMut<Evaluation>,
+    mut ev_generation_ended: EventReader<GenerationEndedEvent>,
+    mut ev_exit: EventWriter<AppExit>,
+) {
+    for generation_ended in ev_generation_ended.iter() {
+        let fitness = generation_ended
+            .fitness
+            .first()
+            .copied()
+            .unwrap_or_default();
+        info!(
+            "Episode {}: fitness {fitness:.1}",
+            generation_ended.generation + 1
+        );
+        evaluation.fitness.push(fitness);
+    }
+    if evaluation.fitness.len() < evaluation.episodes as usize || evaluation.episodes == 0 {
+        return;
+    }
+
+    let episodes = evaluation.fitness.len() as f32;
+    let mean = evaluation.fitness.iter().sum::<f32>() / episodes;
+    let variance = evaluation
+        .fitness
+        .iter()
+        .map(|fitness| (fitness - mean).powi(2))
+        .sum::<f32>()
+        / episodes;
+    let best = evaluation.fitness.iter().copied().fold(f32::MIN, f32::max);
+    let worst = evaluation.fitness.iter().copied().fold(f32::MAX, f32::min);
+    println!(
+        "Evaluated {episodes} episodes: mean {mean:.1}, std dev {:.1}, best {best:.1}, worst {worst:.1}",
+        variance.sqrt()
+    );
+    evaluation.episodes = 0;
+    ev_exit.send(AppExit);
+}
+
+/// Levels of the `i`th child of the survivors
+fn breed(
+    survivors: &[Vec<NetworkLevel>],
+    i: usize,
+    crossover: Option<Crossover>,
+    rng: &mut impl Rng,
+) -> Vec<NetworkLevel> {
+    let clone = || survivors[i % survivors.len()].clone();
+    let Some(crossover) = crossover.filter(|_| survivors.len() > 1) else {
+        return clone();
+    };
+    let parents: Vec<&[NetworkLevel]> = survivors
+        .choose_multiple(rng, 2)
+        .map(Vec::as_slice)
+        .collect();
+    NeuralNetwork::crossover(&parents, crossover, rng).unwrap_or_else(|e| {
+        warn!("Crossover failed, cloning a survivor instead: {e}");
+        clone()
+    })
+}
@@ -0,0 +1,29 @@
+use crate::events::GenerationEndedEvent;
+use crate::resources::{FitnessHistory, GenerationRecord, HistoryExport};
+use bevy::prelude::*;
+
+/// Adds the fitness statistics and the crash causes of every finished generation to the
+/// history
+pub fn record(
+    mut history: ResMut<FitnessHistory>,
+    mut ev_generation_ended: EventReader<GenerationEndedEvent>,
+) {
+    for generation_ended in ev_generation_ended.iter() {
+        history.records.extend(GenerationRecord::new(
+            generation_ended.generation,
+            &generation_ended.fitness,
+            generation_ended.crashes,
+            generation_ended.end_reason,
+        ));
+    }
+}
+
+/// Rewrites the whole history as CSV whenever a generation is added to it
+pub fn export(history: Res<FitnessHistory>, export: Res<HistoryExport>) {
+    if !history.is_changed() || history.records.is_empty() {
+        return;
+    }
+    if let Err(e) = std::fs::write(&export.0, history.to_csv()) {
+        warn!("Could not write the history to {}: {e}", export.0.display());
+    }
+}
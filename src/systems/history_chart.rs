@@ -0,0 +1,199 @@
+use super::network_panel::line_node;
+use crate::components::{ExportHistoryButton, HistoryChart};
+use crate::events::NotificationEvent;
+use crate::query_filters;
+use crate::resources::{FitnessHistory, GenerationRecord, HistoryExport};
+use bevy::prelude::*;
+use std::path::Path;
+
+const CHART_SIZE: Vec2 = Vec2::new(240., 120.);
+/// Space above the plot for the legend and the export button
+const HEADER_HEIGHT: f32 = 18.;
+const PADDING: f32 = 6.;
+/// Generations plotted at most, evenly picked from the whole history
+const MAX_POINTS: usize = 60;
+/// Where the history is exported when `--history` isn't given
+const DEFAULT_EXPORT: &str = "assets/history.csv";
+/// Name, color and value of every plotted line
+type Series = (&'static str, Color, fn(&GenerationRecord) -> f32);
+const SERIES: [Series; 3] = [
+    ("best", Color::rgb(1., 0.85, 0.), |record| record.best),
+    ("median", Color::rgb(0., 0.6, 1.), |record| record.median),
+    ("mean", Color::rgb(0.8, 0.8, 0.8), |record| record.mean),
+];
+
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands
+        .spawn((
+            HistoryChart::default(),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // Right below the network panel
+                    top: Val::Px(210.),
+                    right: Val::Px(5.),
+                    width: Val::Px(CHART_SIZE.x),
+                    height: Val::Px(CHART_SIZE.y),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.6)),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|parent| spawn_header(parent, &font));
+}
+
+/// Redraws the chart whenever a generation is added to the history
+pub fn update(
+    mut commands: Commands,
+    mut chart_q: Query<(Entity, &mut HistoryChart, &mut Visibility)>,
+    asset_server: Res<AssetServer>,
+    history: Res<FitnessHistory>,
+) {
+    let Ok((chart_id, mut chart, mut visibility)) = chart_q.get_single_mut() else {
+        return;
+    };
+    let records = &history.records;
+    if chart.generations == records.len() {
+        return;
+    }
+    chart.generations = records.len();
+    if records.len() < 2 {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Inherited;
+
+    let points: Vec<&GenerationRecord> = (0..records.len().min(MAX_POINTS))
+        .map(|i| &records[i * (records.len() - 1) / (records.len().min(MAX_POINTS) - 1)])
+        .collect();
+    let (min, max) = points
+        .iter()
+        .flat_map(|record| SERIES.iter().map(|(_, _, value)| value(record)))
+        .fold((0f32, f32::MIN), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    let range = (max - min).max(1.);
+    let position = |index: usize, value: f32| {
+        let plot = CHART_SIZE - Vec2::new(2. * PADDING, 2. * PADDING + HEADER_HEIGHT);
+        Vec2::new(
+            PADDING + index as f32 * plot.x / (points.len() - 1) as f32,
+            CHART_SIZE.y - PADDING - (value - min) / range * plot.y,
+        )
+    };
+
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands
+        .entity(chart_id)
+        .despawn_descendants()
+        .with_children(|parent| {
+            spawn_header(parent, &font);
+            for (_, color, value) in SERIES {
+                for (index, pair) in points.windows(2).enumerate() {
+                    let start = position(index, value(pair[0]));
+                    let end = position(index + 1, value(pair[1]));
+                    parent.spawn(NodeBundle {
+                        background_color: BackgroundColor(color),
+                        ..line_node(start, end)
+                    });
+                }
+            }
+            parent.spawn(
+                TextBundle::from_section(format!("{max:.0}"), text_style(&font, Color::GRAY))
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(PADDING),
+                        top: Val::Px(HEADER_HEIGHT),
+                        ..default()
+                    }),
+            );
+        });
+}
+
+/// Writes the history as CSV, where `--history` keeps it updated when given
+pub fn export_handler(
+    mut interaction_q: Query<(&Interaction, &mut BorderColor), query_filters::ExportHistoryButton>,
+    history: Res<FitnessHistory>,
+    export: Option<Res<HistoryExport>>,
+    mut ev_notification: EventWriter<NotificationEvent>,
+) {
+    for (interaction, mut border_color) in &mut interaction_q {
+        match interaction {
+            Interaction::Pressed => {
+                border_color.0 = Color::YELLOW_GREEN;
+                let path = export
+                    .as_ref()
+                    .map_or(Path::new(DEFAULT_EXPORT), |export| export.0.as_path());
+                let notification = match std::fs::write(path, history.to_csv()) {
+                    Ok(()) => NotificationEvent {
+                        message: format!(
+                            "Exported {} generations to {}",
+                            history.records.len(),
+                            path.display()
+                        ),
+                        is_error: false,
+                    },
+                    Err(e) => NotificationEvent {
+                        message: format!("Export failed: {}: {e}", path.display()),
+                        is_error: true,
+                    },
+                };
+                ev_notification.send(notification);
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::CYAN;
+            }
+            Interaction::None => {
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+/// Legend of the series and the export button
+fn spawn_header(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    parent.spawn(
+        TextBundle::from_sections(SERIES.iter().map(|(name, color, _)| {
+            TextSection::new(format!("{name} "), text_style(font, *color))
+        }))
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(PADDING),
+            top: Val::Px(2.),
+            ..default()
+        }),
+    );
+    parent
+        .spawn((
+            ExportHistoryButton,
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(2.),
+                    top: Val::Px(2.),
+                    padding: UiRect::horizontal(Val::Px(4.)),
+                    border: UiRect::all(Val::Px(1.)),
+                    ..default()
+                },
+                border_color: BorderColor(Color::BLACK),
+                background_color: BackgroundColor(Color::ANTIQUE_WHITE),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "CSV",
+                text_style(font, Color::BLACK),
+            ));
+        });
+}
+
+fn text_style(font: &Handle<Font>, color: Color) -> TextStyle {
+    TextStyle {
+        font: font.clone(),
+        font_size: 12.,
+        color,
+    }
+}
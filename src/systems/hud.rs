@@ -0,0 +1,87 @@
+use super::time_control;
+use crate::components::{Car, CarCollided, CarStats, FitnessScore, HudText};
+use crate::query_filters;
+use crate::resources::{Config, Generation, Recorder, SimulationStats};
+use bevy::prelude::*;
+use std::fmt::Write;
+
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands.spawn((
+        HudText,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font,
+                font_size: 12.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(5.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0., 0., 0., 0.6)),
+    ));
+}
+
+/// Counts the fixed steps, the simulation ticks, to measure how many run every second
+pub fn count_tick(mut stats: ResMut<SimulationStats>) {
+    stats.ticks += 1;
+}
+
+/// Measures the frame and tick rates once a second
+pub fn measure_rates(mut stats: ResMut<SimulationStats>, time: Res<Time>) {
+    stats.frames += 1;
+    if stats.measure.tick(time.raw_delta()).just_finished() {
+        let elapsed = stats.measure.duration().as_secs_f32();
+        stats.frames_per_second = stats.frames as f32 / elapsed;
+        stats.ticks_per_second = stats.ticks as f32 / elapsed;
+        stats.frames = 0;
+        stats.ticks = 0;
+    }
+}
+
+/// Refreshes the statistics overlay
+#[allow(clippy::too_many_arguments)]
+pub fn update(
+    mut hud_q: Query<&mut Text, With<HudText>>,
+    cars_q: Query<(&FitnessScore, Option<&CarCollided>), query_filters::Population>,
+    leader_q: Query<(&Car, &CarStats), query_filters::FollowedCar>,
+    config: Res<Config>,
+    generation: Res<Generation>,
+    mut stats: ResMut<SimulationStats>,
+    recorder: Res<Recorder>,
+    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
+) {
+    let mut alive = 0;
+    for (fitness, collided) in &cars_q {
+        alive += usize::from(collided.is_none());
+        stats.best_fitness = stats.best_fitness.max(fitness.0);
+    }
+    let Ok(mut text) = hud_q.get_single_mut() else {
+        return;
+    };
+
+    let hud = &mut text.sections[0].value;
+    hud.clear();
+    let _ = writeln!(hud, "Generation {}", generation.count);
+    let _ = writeln!(hud, "Alive      {alive}/{}", cars_q.iter().len());
+    if let Some((car, car_stats)) = leader_q.iter().next() {
+        let _ = writeln!(hud, "Distance   {:.0}", car_stats.distance);
+        let _ = writeln!(hud, "Speed      {:.1}", car.speed);
+    }
+    let _ = writeln!(hud, "Best       {:.0}", stats.best_fitness);
+    let _ = writeln!(hud, "Traffic    {}", config.current_traffic);
+    let _ = writeln!(hud, "Ticks/s    {:.0}", stats.ticks_per_second);
+    let _ = writeln!(hud, "FPS        {:.0}", stats.frames_per_second);
+    if recorder.enabled {
+        let samples = recorder.session.as_ref().map_or(0, |(_, s)| s.samples());
+        let _ = writeln!(hud, "Recording  {samples} ticks");
+    }
+    hud.push_str(&time_control::describe(&time, &stats, &fixed_time));
+}
@@ -1,8 +1,11 @@
-use crate::components::Controls;
-use bevy::prelude::{Input, KeyCode, Query, Res};
+use crate::components::{Controls, HumanDriver};
+use bevy::prelude::{Input, KeyCode, Query, Res, With};
 
-#[allow(unused)]
-pub fn read_input(keyboard_input: Res<Input<KeyCode>>, mut controls_q: Query<&mut Controls>) {
+/// Drives the human car with the arrow keys
+pub fn read_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut controls_q: Query<&mut Controls, With<HumanDriver>>,
+) {
     for mut controls in &mut controls_q {
         controls.acceleration = 0.;
         controls.turn_direction = 0.;
@@ -0,0 +1,120 @@
+use super::car::spawn_controllable_car;
+use crate::components::{
+    Car, CarCollided, CarsArray, HumanDriver, ManualDrivingButton, NeuralNetwork,
+};
+use crate::query_filters;
+use crate::resources::{ManualDriving, NetworkConfig, RoadProperties, WindowSize};
+use crate::road::RoadPath;
+use bevy::prelude::*;
+
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands
+        .spawn((
+            ManualDrivingButton,
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // Right below the time controls
+                    top: Val::Px(205.),
+                    left: Val::Px(5.),
+                    padding: UiRect::horizontal(Val::Px(6.)),
+                    border: UiRect::all(Val::Px(1.)),
+                    ..default()
+                },
+                border_color: BorderColor(Color::BLACK),
+                background_color: BackgroundColor(Color::ANTIQUE_WHITE),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                ManualDriving::default().to_string(),
+                TextStyle {
+                    font,
+                    font_size: 14.,
+                    color: Color::BLACK,
+                },
+            ));
+        });
+}
+
+/// `M` switches between no human car, one among the networks and one driving alone
+pub fn keyboard(keyboard_input: Res<Input<KeyCode>>, mut mode: ResMut<ManualDriving>) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        *mode = mode.next();
+    }
+}
+
+pub fn button(
+    mut interaction_q: Query<(&Interaction, &mut BorderColor), query_filters::ManualDrivingButton>,
+    mut mode: ResMut<ManualDriving>,
+) {
+    for (interaction, mut border_color) in &mut interaction_q {
+        match interaction {
+            Interaction::Pressed => {
+                border_color.0 = Color::YELLOW_GREEN;
+                *mode = mode.next();
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::CYAN;
+            }
+            Interaction::None => {
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+/// Spawns the human car whenever it's missing, the population being respawned with every
+/// generation, and hides the networks while driving alone
+#[allow(clippy::too_many_arguments)]
+pub fn sync(
+    mut commands: Commands,
+    mode: Res<ManualDriving>,
+    cars_array_q: Query<Entity, With<CarsArray>>,
+    human_q: Query<(Entity, Option<&CarCollided>), With<HumanDriver>>,
+    mut networks_q: Query<&mut Visibility, (With<Car>, With<NeuralNetwork>)>,
+    mut label_q: Query<&mut Text>,
+    button_q: Query<&Children, With<ManualDrivingButton>>,
+    window_size: Res<WindowSize>,
+    road: Res<RoadProperties>,
+    path: Res<RoadPath>,
+    network_config: Res<NetworkConfig>,
+) {
+    if mode.is_changed() {
+        for child in button_q.iter().flatten() {
+            if let Ok(mut text) = label_q.get_mut(*child) {
+                text.sections[0].value = mode.to_string();
+            }
+        }
+    }
+
+    let mut driving = false;
+    if *mode == ManualDriving::Off {
+        for (human_id, _) in &human_q {
+            commands.entity(human_id).despawn_recursive();
+        }
+    } else if human_q.is_empty() {
+        if let Ok(cars_array) = cars_array_q.get_single() {
+            commands.entity(cars_array).with_children(|parent| {
+                spawn_controllable_car(parent, &window_size, &road, &path, &network_config, |_| {
+                    HumanDriver
+                });
+            });
+        }
+    } else {
+        driving = human_q.iter().any(|(_, collided)| collided.is_none());
+    }
+
+    let networks_visibility = if *mode == ManualDriving::Alone && driving {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut visibility in &mut networks_q {
+        if *visibility != networks_visibility {
+            *visibility = networks_visibility;
+        }
+    }
+}
@@ -1,6 +1,18 @@
 pub(super) mod car;
+pub(super) mod config;
+pub(super) mod fitness;
+pub(super) mod generation;
+pub(super) mod history;
+pub(super) mod history_chart;
+pub(super) mod hud;
 pub(super) mod keyboard_input;
+pub(super) mod manual_driving;
 pub(super) mod network;
+pub(super) mod network_panel;
+pub(super) mod playback;
 pub(super) mod ray_cast;
+pub(super) mod recorder;
+pub(super) mod replay;
 pub(super) mod road;
+pub(super) mod time_control;
 pub(super) mod ui;
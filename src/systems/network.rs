@@ -1,4 +1,4 @@
-use crate::components::{CarCollided, Controls, NeuralNetwork, Ray};
+use crate::components::{CarCollided, Controls, NeuralNetwork, Ray, CONTROL_COUNT};
 use bevy::prelude::{Commands, Entity, Query, Without};
 
 /// For each network, use the children rays offset values as initial input for the controls
@@ -11,17 +11,7 @@ pub fn update(
         let mut input_offsets: Vec<f32> = vec![];
         for (idx, ray) in brain.input_rays.iter().enumerate() {
             if let Ok(r) = rays_q.get(*ray) {
-                let offset = if r.collisions.is_empty() {
-                    -1.
-                } else {
-                    1. - r
-                        .collisions
-                        .iter()
-                        .min_by(|a, b| a.1.total_cmp(&b.1))
-                        .unwrap()
-                        .1
-                };
-                input_offsets.insert(idx, offset);
+                input_offsets.insert(idx, r.input());
             } else {
                 commands.entity(entity).despawn();
                 continue 'control_loop;
@@ -31,11 +21,14 @@ pub fn update(
 
         controls.acceleration = 0.;
         controls.turn_direction = 0.;
-        if outputs.len() == 4 {
+        if outputs.len() == CONTROL_COUNT {
             controls.acceleration += outputs[0];
             controls.acceleration -= outputs[3];
             controls.turn_direction += outputs[1];
             controls.turn_direction -= outputs[2];
         }
+        // Continuous activations may push the controls beyond their range
+        controls.acceleration = controls.acceleration.clamp(-1., 1.);
+        controls.turn_direction = controls.turn_direction.clamp(-1., 1.);
     }
 }
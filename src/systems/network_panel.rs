@@ -0,0 +1,179 @@
+use crate::components::{NetworkEdge, NetworkNode, NetworkPanel, NeuralNetwork};
+use crate::query_filters;
+use bevy::prelude::*;
+
+const PANEL_SIZE: Vec2 = Vec2::new(240., 200.);
+const PADDING: f32 = 10.;
+/// Space above the output neurons for their labels
+const LABELS_HEIGHT: f32 = 16.;
+const NODE_SIZE: f32 = 10.;
+const OUTPUT_LABELS: [&str; 4] = ["accel", "left", "right", "brake"];
+
+pub fn setup(mut commands: Commands) {
+    commands.spawn((
+        NetworkPanel::default(),
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.),
+                right: Val::Px(5.),
+                width: Val::Px(PANEL_SIZE.x),
+                height: Val::Px(PANEL_SIZE.y),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.6)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Draws the levels of the followed car, rebuilding the panel whenever the shape of the
+/// network changes
+pub fn update(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut panel_q: Query<(Entity, &mut NetworkPanel, &mut Visibility)>,
+    brain_q: Query<&NeuralNetwork, query_filters::CameraTarget>,
+    mut nodes_q: Query<(&NetworkNode, &mut BackgroundColor, &mut BorderColor)>,
+    mut edges_q: Query<(&NetworkEdge, &mut BackgroundColor, &mut Transform), Without<NetworkNode>>,
+) {
+    let Ok((panel_id, mut panel, mut panel_visibility)) = panel_q.get_single_mut() else {
+        return;
+    };
+    let Some(brain) = brain_q.iter().next() else {
+        *panel_visibility = Visibility::Hidden;
+        return;
+    };
+    *panel_visibility = Visibility::Inherited;
+
+    let shape = network_shape(brain);
+    if panel.shape != shape {
+        let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+        commands
+            .entity(panel_id)
+            .despawn_descendants()
+            .with_children(|parent| spawn_network(parent, &shape, &font));
+        panel.shape = shape;
+        return;
+    }
+
+    for (node, mut background, mut border) in &mut nodes_q {
+        let (value, bias) = if node.layer == 0 {
+            (brain.levels[0].inputs[node.index], None)
+        } else {
+            let level = &brain.levels[node.layer - 1];
+            (level.outputs[node.index], Some(level.biases[node.index]))
+        };
+        background.0 = value_color(value);
+        border.0 = bias.map_or(Color::GRAY, value_color);
+    }
+    for (edge, mut background, mut xform) in &mut edges_q {
+        let weight = brain.levels[edge.level].weights[edge.from][edge.to];
+        background.0 = value_color(weight);
+        xform.scale.y = 1. + 2. * weight.abs().min(1.);
+    }
+}
+
+fn network_shape(brain: &NeuralNetwork) -> Vec<usize> {
+    let mut shape: Vec<usize> = brain.levels.iter().map(|l| l.inputs.len()).collect();
+    shape.extend(brain.levels.last().map(|l| l.outputs.len()));
+    shape
+}
+
+/// Center of a neuron inside the panel, inputs at the bottom and outputs at the top
+fn node_position(shape: &[usize], layer: usize, index: usize) -> Vec2 {
+    let width = PANEL_SIZE.x - 2. * PADDING;
+    let height = PANEL_SIZE.y - 2. * PADDING - LABELS_HEIGHT;
+    let layer_gap = height / (shape.len() - 1).max(1) as f32;
+    Vec2::new(
+        PADDING + (index as f32 + 0.5) * width / shape[layer] as f32,
+        PANEL_SIZE.y - PADDING - layer as f32 * layer_gap,
+    )
+}
+
+fn spawn_network(parent: &mut ChildBuilder, shape: &[usize], font: &Handle<Font>) {
+    // Edges first so the neurons are drawn on top of them
+    for level in 0..shape.len() - 1 {
+        for from in 0..shape[level] {
+            for to in 0..shape[level + 1] {
+                let start = node_position(shape, level, from);
+                let end = node_position(shape, level + 1, to);
+                parent.spawn((NetworkEdge { level, from, to }, line_node(start, end)));
+            }
+        }
+    }
+
+    for (layer, &count) in shape.iter().enumerate() {
+        for index in 0..count {
+            let position = node_position(shape, layer, index);
+            parent.spawn((
+                NetworkNode { layer, index },
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(position.x - NODE_SIZE / 2.),
+                        top: Val::Px(position.y - NODE_SIZE / 2.),
+                        width: Val::Px(NODE_SIZE),
+                        height: Val::Px(NODE_SIZE),
+                        border: UiRect::all(Val::Px(2.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+        }
+    }
+
+    let outputs = shape.len() - 1;
+    if shape[outputs] != OUTPUT_LABELS.len() {
+        return;
+    }
+    for (index, label) in OUTPUT_LABELS.iter().enumerate() {
+        let position = node_position(shape, outputs, index);
+        parent.spawn(
+            TextBundle::from_section(
+                *label,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 12.,
+                    color: Color::WHITE,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(position.x - 18.),
+                top: Val::Px(position.y - NODE_SIZE / 2. - LABELS_HEIGHT),
+                ..default()
+            }),
+        );
+    }
+}
+
+/// A 1px high node going from `start` to `end`, positions being relative to its parent
+pub(super) fn line_node(start: Vec2, end: Vec2) -> NodeBundle {
+    let (center, delta) = ((start + end) / 2., end - start);
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(center.x - delta.length() / 2.),
+            top: Val::Px(center.y - 0.5),
+            width: Val::Px(delta.length()),
+            height: Val::Px(1.),
+            ..default()
+        },
+        // The layout only sets the translation, the rotation is kept
+        transform: Transform::from_rotation(Quat::from_rotation_z(delta.y.atan2(delta.x))),
+        ..default()
+    }
+}
+
+/// Yellow for positive values and blue for negative ones, fading out towards zero
+fn value_color(value: f32) -> Color {
+    let alpha = value.abs().clamp(0.05, 1.);
+    if value >= 0. {
+        Color::rgba(1., 0.85, 0., alpha)
+    } else {
+        Color::rgba(0., 0.6, 1., alpha)
+    }
+}
@@ -0,0 +1,241 @@
+use super::road::{follow_road, RoadPieces};
+use super::time_control;
+use crate::brain::{write_brain, Brain, BrainEncoding, BrainMetadata, BRAINS_DIR};
+use crate::components::{
+    ControllableCarBundle, PlaybackText, ReplayCar, ReplayTraffic, RoadPiece, TrafficCarBundle,
+};
+use crate::replay::KEYFRAME_INTERVAL;
+use crate::resources::{Playback, SimulationStats, WindowSize};
+use crate::road::RoadPath;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use std::fmt::Write;
+
+pub fn setup(mut commands: Commands, playback: Res<Playback>, asset_server: Res<AssetServer>) {
+    commands.spawn(Camera2dBundle::default());
+    for index in 0..playback.scene.cars.len() {
+        commands.spawn((
+            ControllableCarBundle::new(Transform::default(), 0.),
+            ReplayCar(index),
+        ));
+    }
+
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands.spawn((
+        PlaybackText,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font,
+                font_size: 12.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(5.0),
+            left: Val::Px(5.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0., 0., 0., 0.6)),
+    ));
+}
+
+/// Drives the scene through one tick, stopping at the end of the replay
+pub fn advance(mut playback: ResMut<Playback>, path: Res<RoadPath>) {
+    let playback = &mut *playback;
+    playback.scene.advance(&playback.replay, &path);
+}
+
+/// Left and right seek a second back and forth, ten with shift, Home and End jump to the
+/// start and the end, Tab follows the next car still driving, the previous one with shift,
+/// and `S` saves the brain of the followed car
+pub fn keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut playback: ResMut<Playback>,
+    path: Res<RoadPath>,
+) {
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let seek_step = if shift { 10 } else { 1 } * KEYFRAME_INTERVAL;
+    let (tick, last_tick) = (playback.scene.tick, playback.replay.last_tick());
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        playback.seek(tick.saturating_sub(seek_step), &path);
+    }
+    if keyboard_input.just_pressed(KeyCode::Right) {
+        playback.seek((tick + seek_step).min(last_tick), &path);
+    }
+    if keyboard_input.just_pressed(KeyCode::Home) {
+        playback.seek(0, &path);
+    }
+    if keyboard_input.just_pressed(KeyCode::End) {
+        playback.seek(last_tick, &path);
+    }
+
+    let count = playback.scene.cars.len();
+    if keyboard_input.just_pressed(KeyCode::Tab) && count > 0 {
+        let followed = playback.followed;
+        let next = |offset: usize| {
+            if shift {
+                (followed + count - offset) % count
+            } else {
+                (followed + offset) % count
+            }
+        };
+        // Crashed cars are only followed when none is left driving
+        let cars = &playback.scene.cars;
+        let next_car = (1..=count)
+            .map(next)
+            .find(|index| !cars[*index].crashed)
+            .unwrap_or_else(|| next(1));
+        playback.followed = next_car;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::S) && count > 0 {
+        playback.message = Some(match save_followed_brain(&playback, &path) {
+            Ok(path) => format!("Saved {}", path.display()),
+            Err(e) => format!("Saving failed: {e}"),
+        });
+    }
+}
+
+/// Writes the brain of the followed car into the library, with its fitness at the last
+/// keyframe
+fn save_followed_brain(
+    playback: &Playback,
+    path: &RoadPath,
+) -> Result<std::path::PathBuf, crate::brain::PersistenceError> {
+    let (replay, index) = (&playback.replay, playback.followed);
+    let (start_progress, _) = path.project(Vec3::from_array(
+        replay.keyframes[0].cars[index].translation,
+    ));
+    let (progress, _) = path.project(playback.scene.cars[index].transform.translation);
+    let distance = progress - start_progress;
+    let brain = Brain {
+        metadata: BrainMetadata::now(
+            replay.generation,
+            replay.fitness(index, playback.scene.tick),
+            distance,
+            replay.seed,
+            replay.network.clone(),
+        ),
+        levels: replay.levels(index),
+    };
+    write_brain(BRAINS_DIR, &brain, BrainEncoding::default())
+}
+
+/// Moves the cars to where they are in the scene, spawning and despawning the traffic
+pub fn sync(
+    mut commands: Commands,
+    playback: Res<Playback>,
+    mut cars_q: Query<(&ReplayCar, &mut Transform, &mut Sprite), Without<ReplayTraffic>>,
+    mut traffic_q: Query<(Entity, &ReplayTraffic, &mut Transform), Without<ReplayCar>>,
+) {
+    if !playback.is_changed() {
+        return;
+    }
+    for (car, mut car_xform, mut car_sprite) in &mut cars_q {
+        let Some(scene_car) = playback.scene.cars.get(car.0) else {
+            continue;
+        };
+        *car_xform = scene_car.transform;
+        car_sprite.color = if car.0 == playback.followed {
+            // Drawn above the other cars
+            car_xform.translation.z = 1.;
+            Color::YELLOW_GREEN
+        } else if scene_car.crashed {
+            Color::DARK_GRAY
+        } else {
+            Color::rgba_u8(55, 150, 55, 125)
+        };
+    }
+
+    let mut shown = HashSet::new();
+    for (traffic_id, traffic, mut traffic_xform) in &mut traffic_q {
+        match playback.scene.traffic.iter().find(|t| t.id == traffic.0) {
+            Some(scene_traffic) => {
+                *traffic_xform = scene_traffic.transform;
+                shown.insert(traffic.0);
+            }
+            None => commands.entity(traffic_id).despawn(),
+        }
+    }
+    for scene_traffic in &playback.scene.traffic {
+        if shown.contains(&scene_traffic.id) {
+            continue;
+        }
+        commands.spawn((
+            TrafficCarBundle::with_speed(
+                scene_traffic.traffic_car,
+                scene_traffic.transform,
+                scene_traffic.car.max_speed,
+            ),
+            ReplayTraffic(scene_traffic.id),
+        ));
+    }
+}
+
+/// Centers the camera and the road on the followed car
+pub fn follow(
+    playback: Res<Playback>,
+    path: Res<RoadPath>,
+    mut camera_q: Query<&mut Transform, (With<Camera2d>, Without<RoadPiece>)>,
+    mut pieces_q: RoadPieces,
+    window_size: Res<WindowSize>,
+) {
+    let Some(followed) = playback.scene.cars.get(playback.followed) else {
+        return;
+    };
+    let (progress, _) = path.project(followed.transform.translation);
+    follow_road(
+        &path,
+        progress,
+        window_size.1,
+        &mut camera_q.single_mut(),
+        &mut pieces_q,
+    );
+}
+
+pub fn update_text(
+    mut text_q: Query<&mut Text, With<PlaybackText>>,
+    playback: Res<Playback>,
+    time: Res<Time>,
+    stats: Res<SimulationStats>,
+    fixed_time: Res<FixedTime>,
+) {
+    let Ok(mut text) = text_q.get_single_mut() else {
+        return;
+    };
+    let (replay, scene) = (&playback.replay, &playback.scene);
+    let clock = |tick: usize| {
+        let seconds = tick as f32 * replay.delta;
+        format!("{}:{:04.1}", (seconds / 60.) as u32, seconds % 60.)
+    };
+    let driving = scene.cars.iter().filter(|car| !car.crashed).count();
+
+    let text = &mut text.sections[0].value;
+    text.clear();
+    let _ = writeln!(text, "Replay     generation {}", replay.generation);
+    let _ = writeln!(text, "Seed       {}", replay.seed);
+    let _ = writeln!(
+        text,
+        "Time       {}/{}",
+        clock(scene.tick),
+        clock(replay.last_tick())
+    );
+    let _ = writeln!(text, "Alive      {driving}/{}", scene.cars.len());
+    if let Some(car) = scene.cars.get(playback.followed) {
+        let crashed = if car.crashed { ", crashed" } else { "" };
+        let _ = writeln!(text, "Following  car {}{crashed}", playback.followed);
+        let _ = writeln!(text, "Speed      {:.1}", car.car.speed);
+        let _ = writeln!(
+            text,
+            "Fitness    {:.0}",
+            replay.fitness(playback.followed, scene.tick)
+        );
+    }
+    text.push_str(&time_control::describe(&time, &stats, &fixed_time));
+    if let Some(message) = &playback.message {
+        let _ = write!(text, "\n{message}");
+    }
+}
@@ -1,11 +1,30 @@
-use crate::components::{Ray, StaticCollider};
+use crate::components::{BodySize, Ray, StaticCollider};
 use crate::query_filters;
-use bevy::prelude::{Changed, Children, Color, Entity, Query, Sprite, Transform, Vec2, Visibility};
+use crate::resources::BroadPhase;
+use crate::utils::bounding_radius;
+use bevy::prelude::{
+    Changed, Children, Color, Entity, Query, Res, ResMut, Sprite, Transform, Vec2, Visibility,
+};
+
+/// Indexes every collider in the [`BroadPhase`] at its current position
+pub fn update_broad_phase(
+    mut broad_phase: ResMut<BroadPhase>,
+    colliders_q: Query<(&Transform, &BodySize, Entity), query_filters::Collider>,
+) {
+    broad_phase.rebuild(colliders_q.iter().map(|(xform, size, entity)| {
+        (
+            entity,
+            xform.translation.truncate(),
+            bounding_radius(size.0),
+        )
+    }));
+}
 
 pub fn cast_rays(
     cars_q: Query<(&Transform, &Children), query_filters::ControllableCar>,
     mut rays_q: Query<(&mut Ray, Entity)>,
-    mut colliders_q: Query<(&Transform, &Sprite, Entity, &mut StaticCollider)>,
+    mut colliders_q: Query<(&Transform, &BodySize, Entity, &mut StaticCollider)>,
+    broad_phase: Res<BroadPhase>,
 ) {
     for (car_xform, car_children) in cars_q.iter() {
         for &child in car_children {
@@ -13,52 +32,69 @@ pub fn cast_rays(
                 continue;
             };
 
-            for (collider_xform, collider_sprite, collider_id, mut static_collider) in
-                &mut colliders_q
-            {
-                // No reason to check if the collider is too far from the ray
-                if car_xform.translation.distance(collider_xform.translation) >= ray.length * 1.5 {
+            let car_y = car_xform.translation.y;
+            let mut hit = Vec::new();
+            for candidate in broad_phase.query(car_y - ray.length, car_y + ray.length) {
+                let Ok((collider_xform, collider_size, collider_id, mut static_collider)) =
+                    colliders_q.get_mut(candidate)
+                else {
                     continue;
-                }
-                if let Some(collider_size) = collider_sprite.custom_size {
-                    let collider_index = ray.collisions.iter().position(|(e, _)| *e == collider_id);
+                };
+                let collider_index = ray.collisions.iter().position(|(e, _)| *e == collider_id);
+                // No reason to check if the collider is too far from the ray, it's a miss
+                // that still has to clear a collision recorded while it was in range
+                let intersection = if car_xform.translation.distance(collider_xform.translation)
+                    >= ray.length + bounding_radius(collider_size.0)
+                {
+                    None
+                } else {
+                    ray.get_intersecting_point(car_xform, collider_xform, collider_size.0)
+                };
 
-                    match ray.get_intersecting_point(
-                        car_xform,
-                        &collider_xform.translation,
-                        collider_size,
-                    ) {
-                        // If let guard could be useful in here
-                        // they are still experimental: rust-lang/rust/issues/51114
-                        Some(intersection) if matches!(collider_index, Some(_collider_index)) => {
-                            let collider_index = collider_index.unwrap();
-                            // Update existing colliding entity with new collided position
-                            let error_margin = 0.1;
-                            if (intersection.1 - ray.collisions[collider_index].1).abs()
-                                > error_margin
-                            {
-                                ray.collisions.remove(collider_index);
-                                ray.collisions.push((collider_id, intersection.1));
-                            }
-                        }
-                        Some(intersection) => {
-                            static_collider.colliding_with.push(ray_id);
+                if intersection.is_some() {
+                    hit.push(collider_id);
+                }
+                match intersection {
+                    // If let guard could be useful in here
+                    // they are still experimental: rust-lang/rust/issues/51114
+                    Some(intersection) if matches!(collider_index, Some(_collider_index)) => {
+                        let collider_index = collider_index.unwrap();
+                        // Update existing colliding entity with new collided position
+                        let error_margin = 0.1;
+                        if (intersection.1 - ray.collisions[collider_index].1).abs() > error_margin
+                        {
+                            ray.collisions.remove(collider_index);
                             ray.collisions.push((collider_id, intersection.1));
                         }
-                        None => {
-                            if let Some(collider_index) = collider_index {
-                                ray.collisions.remove(collider_index);
-                                let ray_index = static_collider
-                                    .colliding_with
-                                    .iter()
-                                    .position(|e| *e == ray_id)
-                                    .unwrap();
-                                static_collider.colliding_with.remove(ray_index);
-                            }
+                    }
+                    Some(intersection) => {
+                        static_collider.colliding_with.push(ray_id);
+                        ray.collisions.push((collider_id, intersection.1));
+                    }
+                    None => {
+                        if let Some(collider_index) = collider_index {
+                            ray.collisions.remove(collider_index);
+                            static_collider.colliding_with.retain(|e| *e != ray_id);
                         }
                     }
                 }
             }
+
+            // Colliders despawned, or moved beyond the broad phase range like the wrapped
+            // road pieces, are never candidates again and would be seen forever
+            let mut stale = Vec::new();
+            ray.collisions.retain(|(collider_id, _)| {
+                let keep = hit.contains(collider_id);
+                if !keep {
+                    stale.push(*collider_id);
+                }
+                keep
+            });
+            for collider_id in stale {
+                if let Ok((_, _, _, mut static_collider)) = colliders_q.get_mut(collider_id) {
+                    static_collider.colliding_with.retain(|e| *e != ray_id);
+                }
+            }
         }
     }
 }
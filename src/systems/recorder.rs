@@ -0,0 +1,86 @@
+use crate::components::{Car, Controls, Ray};
+use crate::dataset::{DatasetWriter, Sample, DATASETS_DIR};
+use crate::events::NotificationEvent;
+use crate::query_filters;
+use crate::resources::{NetworkConfig, Recorder};
+use bevy::prelude::*;
+
+/// `R` turns the recording of the human driving on and off
+pub fn toggle(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut recorder: ResMut<Recorder>,
+    mut ev_notification: EventWriter<NotificationEvent>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::R) {
+        return;
+    }
+    recorder.enabled = !recorder.enabled;
+    ev_notification.send(NotificationEvent {
+        message: format!("Recording {}", if recorder.enabled { "on" } else { "off" }),
+        is_error: false,
+    });
+}
+
+/// Logs what the human car senses and how it's driven on every fixed tick, a session
+/// lasting until the car crashes or recording is turned off
+pub fn record(
+    mut recorder: ResMut<Recorder>,
+    human_q: Query<(Entity, &Car, &Controls, &Children), query_filters::HumanCar>,
+    rays_q: Query<&Ray>,
+    network_config: Res<NetworkConfig>,
+    mut ev_notification: EventWriter<NotificationEvent>,
+) {
+    let human = human_q.iter().next().filter(|_| recorder.enabled);
+    let same_car =
+        matches!((&recorder.session, human), (Some((car, _)), Some((id, ..))) if *car == id);
+    if !same_car {
+        if let Some((_, session)) = recorder.session.take() {
+            let samples = session.samples();
+            ev_notification.send(match session.finish() {
+                Ok(path) => NotificationEvent {
+                    message: format!("Recorded {samples} ticks to {}", path.display()),
+                    is_error: false,
+                },
+                Err(e) => NotificationEvent {
+                    message: format!("Recording failed: {e}"),
+                    is_error: true,
+                },
+            });
+        }
+    }
+    let Some((car_id, car, controls, children)) = human else {
+        return;
+    };
+    if recorder.session.is_none() {
+        match DatasetWriter::create(DATASETS_DIR, &network_config) {
+            Ok(session) => recorder.session = Some((car_id, session)),
+            Err(e) => {
+                recorder.enabled = false;
+                ev_notification.send(NotificationEvent {
+                    message: format!("Recording failed: {e}"),
+                    is_error: true,
+                });
+                return;
+            }
+        }
+    }
+
+    // Rays are spawned as children in the order the networks read them
+    let sample = Sample {
+        speed: car.speed,
+        rays: rays_q.iter_many(children).map(Ray::input).collect(),
+        acceleration: controls.acceleration,
+        turn_direction: controls.turn_direction,
+    };
+    let Some((_, session)) = &mut recorder.session else {
+        return;
+    };
+    if let Err(e) = session.write(&sample) {
+        recorder.enabled = false;
+        recorder.session = None;
+        ev_notification.send(NotificationEvent {
+            message: format!("Recording failed: {e}"),
+            is_error: true,
+        });
+    }
+}
@@ -0,0 +1,185 @@
+use crate::brain::Level;
+use crate::components::{Car, CarCollided, Controls, FitnessScore, NeuralNetwork, TrafficCar};
+use crate::events::GenerationEndedEvent;
+use crate::query_filters;
+use crate::replay::{
+    write_replay, CarState, Keyframe, Replay, Tick, TrafficState, KEYFRAME_INTERVAL, REPLAY_VERSION,
+};
+use crate::resources::{
+    Config, Generation, NetworkConfig, ReplayRecorder, RoadProperties, SimulationRng,
+};
+use bevy::prelude::*;
+
+type RecordedCars<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Car,
+        &'static Transform,
+        &'static Controls,
+        &'static NeuralNetwork,
+        &'static FitnessScore,
+        Option<&'static CarCollided>,
+    ),
+    query_filters::Population,
+>;
+type RecordedTraffic<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Car,
+        &'static TrafficCar,
+        &'static Transform,
+    ),
+    query_filters::Traffic,
+>;
+
+/// Records what changed since the previous tick and the controls the cars are about to
+/// move with, starting a new replay with every generation or loaded brain
+#[allow(clippy::too_many_arguments)]
+pub fn record(
+    mut recorder: ResMut<ReplayRecorder>,
+    cars_q: RecordedCars,
+    traffic_q: RecordedTraffic,
+    generation: Res<Generation>,
+    config: Res<Config>,
+    network_config: Res<NetworkConfig>,
+    road: Res<RoadProperties>,
+    rng: Res<SimulationRng>,
+    time: Res<FixedTime>,
+) {
+    let recorder = &mut *recorder;
+    let restart = match &recorder.replay {
+        Some(replay) => {
+            replay.generation != generation.count
+                || cars_q
+                    .iter()
+                    .any(|(car_id, ..)| !recorder.cars.contains_key(&car_id))
+        }
+        None => true,
+    };
+    if restart {
+        recorder.cars.clear();
+        recorder.traffic.clear();
+        recorder.next_traffic_id = 0;
+        let mut brains = Vec::new();
+        for (index, (car_id, _, _, _, brain, _, _)) in cars_q.iter().enumerate() {
+            recorder.cars.insert(car_id, index);
+            brains.push(brain.levels.iter().map(Level::from).collect());
+        }
+        recorder.crashed = vec![false; brains.len()];
+        recorder.replay = Some(Replay {
+            version: REPLAY_VERSION,
+            seed: rng.seed(),
+            generation: generation.count,
+            delta: time.period.as_secs_f32(),
+            config: config.clone(),
+            network: network_config.clone(),
+            road: *road,
+            brains,
+            ticks: Vec::new(),
+            keyframes: Vec::new(),
+        });
+    }
+    capture(recorder, &cars_q, &traffic_q);
+}
+
+/// Writes the replay of the generation that just ended, along with the crashes ending it
+pub fn save(
+    mut recorder: ResMut<ReplayRecorder>,
+    cars_q: RecordedCars,
+    traffic_q: RecordedTraffic,
+    mut ev_generation_ended: EventReader<GenerationEndedEvent>,
+) {
+    if ev_generation_ended.is_empty() {
+        return;
+    }
+    ev_generation_ended.clear();
+    capture(&mut recorder, &cars_q, &traffic_q);
+    let Some(replay) = recorder.replay.take() else {
+        return;
+    };
+    match write_replay(&recorder.dir, &replay) {
+        Ok(path) => info!("Replay written to {}", path.display()),
+        Err(e) => warn!("Could not write the replay: {e}"),
+    }
+}
+
+/// Appends a tick to the replay, and a keyframe every [`KEYFRAME_INTERVAL`] ticks
+fn capture(recorder: &mut ReplayRecorder, cars_q: &RecordedCars, traffic_q: &RecordedTraffic) {
+    let ReplayRecorder {
+        replay: Some(replay),
+        cars,
+        crashed,
+        traffic,
+        next_traffic_id,
+        ..
+    } = recorder
+    else {
+        return;
+    };
+    let mut tick = Tick::default();
+    let mut keyframe = (replay.ticks.len() % KEYFRAME_INTERVAL == 0).then(|| Keyframe {
+        cars: vec![CarState::default(); cars.len()],
+        crashed: Vec::new(),
+        fitness: vec![0.; cars.len()],
+        traffic: Vec::new(),
+    });
+
+    for (traffic_id, car, traffic_car, transform) in traffic_q.iter() {
+        let mut state = TrafficState {
+            id: *next_traffic_id,
+            max_speed: car.max_speed,
+            lane: traffic_car.lane,
+            progress: traffic_car.progress,
+            state: CarState::new(car, transform),
+        };
+        match traffic.get(&traffic_id) {
+            Some(id) => state.id = *id,
+            None => {
+                traffic.insert(traffic_id, state.id);
+                *next_traffic_id += 1;
+                tick.spawned.push(state);
+            }
+        }
+        if let Some(keyframe) = &mut keyframe {
+            keyframe.traffic.push(state);
+        }
+    }
+    traffic.retain(|traffic_id, id| {
+        let alive = traffic_q.get(*traffic_id).is_ok();
+        if !alive {
+            tick.despawned.push(*id);
+        }
+        alive
+    });
+    tick.despawned.sort_unstable();
+
+    for (car_id, car, transform, controls, _, fitness, collided) in cars_q.iter() {
+        let Some(&index) = cars.get(&car_id) else {
+            continue;
+        };
+        if collided.is_none() {
+            tick.controls
+                .push((index, controls.acceleration, controls.turn_direction));
+        } else if !crashed[index] {
+            crashed[index] = true;
+            tick.crashed.push(index);
+        }
+        if let Some(keyframe) = &mut keyframe {
+            keyframe.cars[index] = CarState::new(car, transform);
+            keyframe.fitness[index] = fitness.0;
+        }
+    }
+    tick.controls.sort_unstable_by_key(|(index, ..)| *index);
+    tick.crashed.sort_unstable();
+
+    if let Some(mut keyframe) = keyframe {
+        keyframe.crashed = crashed.clone();
+        keyframe.traffic.sort_unstable_by_key(|traffic| traffic.id);
+        replay.keyframes.push(keyframe);
+    }
+    replay.ticks.push(tick);
+}
@@ -1,88 +1,111 @@
-use crate::components::{Pavement, Road, RoadLine, StaticCollider};
+use crate::components::{BodySize, Pavement, Road, RoadLine, RoadPiece, StaticCollider};
 use crate::query_filters;
-use crate::resources::{CameraTarget, RoadProperties, WindowSize};
+use crate::resources::{CameraTarget, Config, RoadProperties, SimulationRng, WindowSize};
+use crate::road::RoadPath;
 use bevy::prelude::*;
 
+pub(super) type RoadPieces<'w, 's> =
+    Query<'w, 's, (&'static mut Transform, &'static mut RoadPiece), Without<Camera2d>>;
+
 pub fn setup(world: &mut World) {
     let window_size = world.remove_resource::<WindowSize>().unwrap();
-    let road = RoadProperties {
-        lane_count: 6,
-        width: window_size.0, // / 2.,
-    };
+    let road = world
+        .get_resource::<RoadProperties>()
+        .copied()
+        .unwrap_or(RoadProperties {
+            lane_count: 6,
+            width: window_size.0, // / 2.,
+        });
 
     world.insert_resource(road);
 
+    // A replay brings the road it was recorded on
+    let mut path = world.remove_resource::<RoadPath>().unwrap_or_else(|| {
+        RoadPath::new(
+            world.resource::<SimulationRng>().seed(),
+            road,
+            world.resource::<Config>().road_curviness,
+        )
+    });
+    path.extend_to(window_size.1);
+    let laid = |arc_length: f32, offset: f32, z: f32| {
+        let mut transform = path.transform_at(arc_length, offset);
+        transform.translation.z = z;
+        transform
+    };
+
+    let dash_size = 40.;
+    // The pavement and the road edges are longer than the dashes so they still overlap on
+    // the outside of the curves
+    let piece_size = dash_size * 1.5;
+    let dash_count = (window_size.1 as u16 * 2) / dash_size as u16;
+    let dash_arc_length = |j: u16| ((f32::from(j) * dash_size) - window_size.1) + dash_size / 2.;
+
     world
         .spawn_empty()
         .insert(SpatialBundle::default())
         .insert(Road)
         .with_children(|parent| {
             // background
-            parent.spawn((
-                Pavement,
-                SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::rgb_u8(80, 80, 80),
-                        custom_size: Some(Vec2 {
-                            x: road.width,
-                            y: window_size.1 * 2.,
-                        }),
-                        ..default()
+            for j in 0..dash_count {
+                let arc_length = dash_arc_length(j);
+                parent.spawn((
+                    Pavement,
+                    RoadPiece {
+                        arc_length,
+                        offset: 0.,
                     },
-                    transform: Transform {
-                        translation: Vec3 {
-                            x: 0., //-road.width / 2.,
-                            y: 0.,
-                            z: -10.,
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgb_u8(80, 80, 80),
+                            custom_size: Some(Vec2 {
+                                x: road.width,
+                                y: piece_size,
+                            }),
+                            ..default()
                         },
+                        transform: laid(arc_length, 0., -10.),
                         ..default()
                     },
-                    ..default()
-                },
-            ));
+                ));
+            }
 
             // lanes
-            let dash_size = 40.;
-            let dash_y_count = (window_size.1 as u16 * 2) / dash_size as u16;
             let lane_width = road.width / (f32::from(road.lane_count));
 
             (0..=road.lane_count).for_each(|i| {
-                for j in 0..dash_y_count {
-                    let y = if i == 0 || i == road.lane_count || j % 2 == 0 {
-                        ((f32::from(j) * dash_size) - window_size.1) + dash_size / 2.
-                    } else {
+                let is_edge = i == 0 || i == road.lane_count;
+                let size = Vec2 {
+                    x: 4.,
+                    y: if is_edge { piece_size } else { dash_size },
+                };
+                let offset = lane_width * f32::from(i) - road.width / 2.;
+                for j in 0..dash_count {
+                    if !is_edge && j % 2 != 0 {
                         continue;
-                    };
+                    }
+                    let arc_length = dash_arc_length(j);
 
                     let mut road_line = parent.spawn((
                         RoadLine,
+                        RoadPiece { arc_length, offset },
+                        BodySize(size),
                         SpriteBundle {
                             sprite: Sprite {
-                                color: if i == 0 || i == road.lane_count {
+                                color: if is_edge {
                                     Color::BLACK
                                 } else {
                                     Color::rgb_u8(185, 185, 185)
                                 },
-                                custom_size: Some(Vec2 {
-                                    x: 4.,
-                                    y: dash_size,
-                                }),
-                                ..default()
-                            },
-                            transform: Transform {
-                                translation: Vec3 {
-                                    //x: -road.width / (road.lane_count as f32) * i as f32,
-                                    x: lane_width * f32::from(i) - road.width / 2.,
-                                    y,
-                                    z: -9.,
-                                },
+                                custom_size: Some(size),
                                 ..default()
                             },
+                            transform: laid(arc_length, offset, -9.),
                             ..default()
                         },
                     ));
 
-                    if i == 0 || i == road.lane_count {
+                    if is_edge {
                         // road margins take a static collider
                         road_line.insert(StaticCollider::default());
                     }
@@ -90,18 +113,18 @@ pub fn setup(world: &mut World) {
             });
         });
 
+    world.insert_resource(path);
     world.insert_resource(window_size);
 }
 
 pub fn move_road(
-    mut dashes_q: Query<&mut Transform, With<RoadLine>>,
-    mut camera_q: Query<&mut Transform, (With<Camera2d>, Without<RoadLine>)>,
-    mut pavement_q: Query<&mut Transform, query_filters::Pavement>,
+    mut pieces_q: RoadPieces,
+    mut camera_q: Query<&mut Transform, (With<Camera2d>, Without<RoadPiece>)>,
     car_q: Query<Option<&Transform>, query_filters::CameraTarget>,
     window_size: Res<WindowSize>,
     camera_target: Res<CameraTarget>,
+    mut path: ResMut<RoadPath>,
 ) {
-    let mut pavement_xform = pavement_q.single_mut();
     let mut camera_xform = camera_q.single_mut();
     if car_q.is_empty() || camera_target.get_target().is_none() {
         return;
@@ -110,24 +133,72 @@ pub fn move_road(
     let Ok(Some(car_xform)) = car_q.get(camera_target.get_target().unwrap()) else {
         return;
     };
-    camera_xform.translation.y = car_xform.translation.y + window_size.1 / 4.;
-    pavement_xform.translation.y = camera_xform.translation.y;
-
-    let y_position_constraints = (
-        camera_xform.translation.y - window_size.1,
-        camera_xform.translation.y + window_size.1,
+    let (car_progress, _) = path.project(car_xform.translation);
+    // Generated past the pieces laid furthest ahead of the camera
+    path.extend_to(car_progress + window_size.1 * 1.25);
+    follow_road(
+        &path,
+        car_progress,
+        window_size.1,
+        &mut camera_xform,
+        &mut pieces_q,
     );
+}
 
-    for mut dash_xform in &mut dashes_q {
-        if (y_position_constraints.0..=y_position_constraints.1).contains(&dash_xform.translation.y)
-        {
-            continue;
-        }
+/// Moves the camera and the road pieces back to the start of the road
+pub fn reset_road(
+    mut pieces_q: RoadPieces,
+    mut colliders_q: Query<&mut StaticCollider, With<RoadLine>>,
+    mut camera_q: Query<&mut Transform, (With<Camera2d>, Without<RoadPiece>)>,
+    path: Res<RoadPath>,
+    window_size: Res<WindowSize>,
+) {
+    let mut camera_xform = camera_q.single_mut();
+    camera_xform.translation.x = 0.;
+    camera_xform.translation.y = 0.;
 
-        if dash_xform.translation.y < y_position_constraints.0 {
-            dash_xform.translation.y += window_size.1 * 2.;
-        } else if dash_xform.translation.y > y_position_constraints.1 {
-            dash_xform.translation.y += -window_size.1 * 2.;
+    // Pieces are always moved by the window height, so laying them around the start places
+    // every piece back where it was originally spawned
+    lay_pieces(&path, 0., window_size.1, &mut pieces_q);
+    for mut static_collider in &mut colliders_q {
+        static_collider.colliding_with.clear();
+    }
+}
+
+/// Centers the camera a quarter of the window ahead of `progress` along the road and lays
+/// the road pieces around it
+pub(super) fn follow_road(
+    path: &RoadPath,
+    progress: f32,
+    window_height: f32,
+    camera_xform: &mut Transform,
+    pieces_q: &mut RoadPieces,
+) {
+    let camera_progress = progress + window_height / 4.;
+    let (camera_position, _) = path.frame_at(camera_progress);
+    camera_xform.translation = camera_position.extend(camera_xform.translation.z);
+    lay_pieces(path, camera_progress, window_height, pieces_q);
+}
+
+/// Moves every road piece by whole window heights along the road to be within a window
+/// height of the camera
+fn lay_pieces(
+    path: &RoadPath,
+    camera_progress: f32,
+    window_height: f32,
+    pieces_q: &mut RoadPieces,
+) {
+    for (mut piece_xform, mut piece) in pieces_q {
+        let arc_length = (piece.arc_length - camera_progress + window_height)
+            .rem_euclid(window_height * 2.)
+            - window_height
+            + camera_progress;
+        if arc_length == piece.arc_length {
+            continue;
         }
+        piece.arc_length = arc_length;
+        let z = piece_xform.translation.z;
+        *piece_xform = path.transform_at(arc_length, piece.offset);
+        piece_xform.translation.z = z;
     }
 }
@@ -0,0 +1,200 @@
+use crate::components::{PauseLabel, TimeAction, TimeControlButton};
+use crate::resources::{EffectiveSpeed, SimulationStats};
+use bevy::prelude::*;
+
+/// Simulation speeds, as multiples of the real time. The fixed step never changes, faster
+/// speeds run more steps per frame so the simulation stays deterministic.
+const SPEEDS: [f32; 9] = [0.25, 0.5, 1., 2., 4., 8., 16., 32., 64.];
+/// Most fixed steps run in a frame, twice what the fastest speed needs at 60 frames per
+/// second
+const MAX_STEPS_PER_FRAME: u32 = 128;
+
+pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // Right below the HUD
+                top: Val::Px(180.),
+                left: Val::Px(5.),
+                column_gap: Val::Px(2.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for (action, label) in [
+                (TimeAction::TogglePause, "Pause"),
+                (TimeAction::Step, "Step"),
+                (TimeAction::Slower, "-"),
+                (TimeAction::Faster, "+"),
+            ] {
+                parent
+                    .spawn((
+                        TimeControlButton(action),
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::horizontal(Val::Px(6.)),
+                                border: UiRect::all(Val::Px(1.)),
+                                ..default()
+                            },
+                            border_color: BorderColor(Color::BLACK),
+                            background_color: BackgroundColor(Color::ANTIQUE_WHITE),
+                            ..default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        let mut text = parent.spawn(TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 14.,
+                                color: Color::BLACK,
+                            },
+                        ));
+                        if action == TimeAction::TogglePause {
+                            text.insert(PauseLabel);
+                        }
+                    });
+            }
+        });
+}
+
+/// Space pauses, `.` steps once, `-` and `+` change the speed
+pub fn keyboard(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut time: ResMut<Time>,
+    mut fixed_time: ResMut<FixedTime>,
+) {
+    let actions = [
+        (KeyCode::Space, TimeAction::TogglePause),
+        (KeyCode::Period, TimeAction::Step),
+        (KeyCode::Minus, TimeAction::Slower),
+        (KeyCode::NumpadSubtract, TimeAction::Slower),
+        (KeyCode::Equals, TimeAction::Faster),
+        (KeyCode::NumpadAdd, TimeAction::Faster),
+    ];
+    for (key, action) in actions {
+        if keyboard_input.just_pressed(key) {
+            apply(action, &mut time, &mut fixed_time);
+        }
+    }
+}
+
+pub fn buttons(
+    mut interaction_q: Query<
+        (&Interaction, &TimeControlButton, &mut BorderColor),
+        Changed<Interaction>,
+    >,
+    mut time: ResMut<Time>,
+    mut fixed_time: ResMut<FixedTime>,
+) {
+    for (interaction, button, mut border_color) in &mut interaction_q {
+        match interaction {
+            Interaction::Pressed => {
+                border_color.0 = Color::YELLOW_GREEN;
+                apply(button.0, &mut time, &mut fixed_time);
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::CYAN;
+            }
+            Interaction::None => {
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+/// Drops the time left to simulate once the frame ran [`MAX_STEPS_PER_FRAME`] steps. Every
+/// step the frame owes runs otherwise, so a frame too slow for its steps makes the next one
+/// owe even more until the app stalls.
+pub fn cap_steps(mut fixed_time: ResMut<FixedTime>, mut speed: ResMut<EffectiveSpeed>) {
+    speed.frame_steps += 1;
+    if speed.frame_steps >= MAX_STEPS_PER_FRAME {
+        while fixed_time.expend().is_ok() {}
+    }
+}
+
+/// Starts counting the steps of the next frame
+pub fn start_frame(mut speed: ResMut<EffectiveSpeed>) {
+    speed.frame_steps = 0;
+}
+
+/// Time scale line of the overlays, with the speed actually reached when the simulation
+/// can't keep up
+pub fn describe(time: &Time, stats: &SimulationStats, fixed_time: &FixedTime) -> String {
+    if time.is_paused() {
+        return "Time scale paused".to_string();
+    }
+    let scale = time.relative_speed();
+    let speed = stats.ticks_per_second * fixed_time.period.as_secs_f32();
+    // No tick rate is measured during the first second
+    if stats.ticks_per_second > 0. && speed < scale * 0.9 {
+        format!("Time scale x{scale:.2}, running x{speed:.2}")
+    } else {
+        format!("Time scale x{scale:.2}")
+    }
+}
+
+/// Shows whether the pause button resumes or pauses the simulation
+pub fn update_pause_label(mut label_q: Query<&mut Text, With<PauseLabel>>, time: Res<Time>) {
+    let label = if time.is_paused() { "Play" } else { "Pause" };
+    for mut text in &mut label_q {
+        if text.sections[0].value != label {
+            text.sections[0].value = label.to_string();
+        }
+    }
+}
+
+fn apply(action: TimeAction, time: &mut Time, fixed_time: &mut FixedTime) {
+    let speed = SPEEDS
+        .iter()
+        .position(|speed| *speed >= time.relative_speed())
+        .unwrap_or(SPEEDS.len() - 1);
+    match action {
+        TimeAction::TogglePause if time.is_paused() => time.unpause(),
+        TimeAction::TogglePause => time.pause(),
+        TimeAction::Step => {
+            time.pause();
+            // Runs exactly one fixed step on the next frame, no time passing while paused
+            let period = fixed_time.period;
+            fixed_time.tick(period);
+        }
+        TimeAction::Slower => time.set_relative_speed(SPEEDS[speed.saturating_sub(1)]),
+        TimeAction::Faster => {
+            time.set_relative_speed(SPEEDS[(speed + 1).min(SPEEDS.len() - 1)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::RunFixedUpdateLoop;
+    use bevy::time::TimePlugin;
+
+    #[test]
+    fn caps_the_steps_owed_by_a_slow_frame() {
+        let mut app = App::new();
+        app.add_plugins(TimePlugin)
+            .insert_resource(FixedTime::new_from_secs(1. / 60.))
+            .init_resource::<EffectiveSpeed>()
+            .add_systems(FixedUpdate, cap_steps);
+
+        let period = app.world.resource::<FixedTime>().period;
+        app.world.resource_mut::<FixedTime>().tick(period * 1000);
+        app.world.run_schedule(RunFixedUpdateLoop);
+        assert_eq!(
+            app.world.resource::<EffectiveSpeed>().frame_steps,
+            MAX_STEPS_PER_FRAME
+        );
+        assert!(app.world.resource::<FixedTime>().accumulated() < period);
+
+        // The next frame gets a whole budget again
+        app.world.resource_mut::<EffectiveSpeed>().frame_steps = 0;
+        app.world.resource_mut::<FixedTime>().tick(period * 3);
+        app.world.run_schedule(RunFixedUpdateLoop);
+        assert_eq!(app.world.resource::<EffectiveSpeed>().frame_steps, 3);
+    }
+}
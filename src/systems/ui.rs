@@ -1,11 +1,14 @@
+use crate::brain::{
+    list_brains, read_brain, write_brain, Brain, BrainEncoding, BrainMetadata, BrainSelection,
+    BRAINS_DIR,
+};
 use crate::components::{
-    CameraFollowMarker, CarCollided, LoadButton, NetworkLevel, NeuralNetwork, SaveButton,
+    BrowseButton, CarStats, EncodingButton, FitnessScore, LoadButton, NeuralNetwork, SaveButton,
+    Toast, ToastArea,
 };
-use crate::{query_filters, AppState, LoadNetworkEvent};
+use crate::resources::{Generation, LoadSelection, NetworkConfig, SaveEncoding, SimulationRng};
+use crate::{query_filters, AppState, LoadNetworkEvent, NotificationEvent};
 use bevy::prelude::*;
-use bevy::reflect::erased_serde::__private::serde::de::DeserializeSeed;
-use bevy::reflect::serde::{TypedReflectDeserializer, TypedReflectSerializer};
-use bevy::reflect::TypeRegistration;
 
 pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
@@ -26,11 +29,17 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             ..default()
         })
         .with_children(|parent| {
+            parent
+                .spawn(new_button(30.0))
+                .insert(BrowseButton)
+                .with_children(|parent| {
+                    parent.spawn(new_text(">", &font));
+                });
             parent
                 .spawn(new_button(140.0))
                 .insert(LoadButton)
                 .with_children(|parent| {
-                    parent.spawn(new_text("Load Recent", &font));
+                    parent.spawn(new_text("Load Latest", &font));
                 });
             parent
                 .spawn(new_button(140.0))
@@ -38,18 +47,41 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 .with_children(|parent| {
                     parent.spawn(new_text("Save Current", &font));
                 });
+            parent
+                .spawn(new_button(70.0))
+                .insert(EncodingButton)
+                .with_children(|parent| {
+                    parent.spawn(new_text(&SaveEncoding::default().0.to_string(), &font));
+                });
         });
+
+    commands.spawn((
+        ToastArea,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                // Above the buttons, leaving the top left corner to the HUD
+                bottom: Val::Px(40.0),
+                left: Val::Px(5.0),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            ..default()
+        },
+    ));
 }
 
 pub fn save_handler(
     mut interaction_q: Query<(&Interaction, &mut BorderColor), query_filters::SaveButton>,
-    brain_q: Query<Option<&NeuralNetwork>, (With<CameraFollowMarker>, Without<CarCollided>)>,
-    type_registry: Res<AppTypeRegistry>,
+    brain_q: Query<(&NeuralNetwork, &FitnessScore, &CarStats), query_filters::FollowedCar>,
+    rng: Res<SimulationRng>,
+    generation: Res<Generation>,
+    network_config: Res<NetworkConfig>,
+    encoding: Res<SaveEncoding>,
+    mut ev_notification: EventWriter<NotificationEvent>,
 ) {
-    if brain_q.is_empty() {
-        return;
-    }
-    let Some(brain) = brain_q.iter().next().and_then(|b| b) else {
+    let Some((network, score, stats)) = brain_q.iter().next() else {
         return;
     };
 
@@ -57,17 +89,33 @@ pub fn save_handler(
         match interaction {
             Interaction::Pressed => {
                 border_color.0 = Color::YELLOW_GREEN;
-                // Serialize the "brain"
-                let type_registry = type_registry.read();
-                let brain_serialized = ron::ser::to_string_pretty(
-                    &TypedReflectSerializer::new(&brain.levels, &type_registry),
-                    ron::ser::PrettyConfig::default(),
-                )
-                .unwrap();
-                std::fs::write("brain", brain_serialized).unwrap();
-                // TODO: Add date and timestamp to the file name
-                // save into a file inside the path assets/brain/TIMESTAMP
-                // and maybe rename the last file to old_TIMESTAMP
+                let brain = Brain {
+                    metadata: BrainMetadata::now(
+                        generation.count,
+                        score.0,
+                        stats.distance,
+                        rng.seed(),
+                        network_config.clone(),
+                    ),
+                    levels: network.levels.clone(),
+                };
+                let notification = match write_brain(BRAINS_DIR, &brain, encoding.0) {
+                    Ok(path) => {
+                        info!("Saved brain {}", path.display());
+                        NotificationEvent {
+                            message: format!(
+                                "Saved gen {}, fitness {:.0}",
+                                generation.count, score.0
+                            ),
+                            is_error: false,
+                        }
+                    }
+                    Err(e) => NotificationEvent {
+                        message: format!("Save failed: {e}"),
+                        is_error: true,
+                    },
+                };
+                ev_notification.send(notification);
             }
             Interaction::Hovered => {
                 border_color.0 = Color::CYAN;
@@ -82,39 +130,31 @@ pub fn save_handler(
 pub fn load_handler(
     mut commands: Commands,
     mut interaction_q: Query<(&Interaction, &mut BorderColor), query_filters::LoadButton>,
-    type_registry: Res<AppTypeRegistry>,
+    selection: Res<LoadSelection>,
     mut ev_load_network: EventWriter<LoadNetworkEvent>,
+    mut ev_notification: EventWriter<NotificationEvent>,
 ) {
     for (interaction, mut border_color) in &mut interaction_q {
         match interaction {
             Interaction::Pressed => {
                 border_color.0 = Color::YELLOW_GREEN;
-                let Some(brain) = (match std::fs::read_to_string("brain") {
-                    Ok(brain_serialized) => {
-                        let type_registry = type_registry.read();
-                        let registration = TypeRegistration::of::<Vec<NetworkLevel>>();
-                        let reflect_deserializer =
-                            TypedReflectDeserializer::new(&registration, &type_registry);
-                        let mut ron_deserializer =
-                            ron::de::Deserializer::from_str(&brain_serialized).unwrap();
-                        let brain_reflection =
-                            match reflect_deserializer.deserialize(&mut ron_deserializer) {
-                                Ok(t) => t,
-                                Err(e) => {
-                                    dbg!("called `Result::unwrap()` on an `Err` value {}", &e);
-                                    Box::new(())
-                                }
-                            };
-                        <Vec<NetworkLevel> as FromReflect>::from_reflect(&*brain_reflection)
-                    }
+                let brain = match selection.0.resolve(BRAINS_DIR).and_then(read_brain) {
+                    Ok(brain) => brain,
                     Err(e) => {
-                        dbg!("Error loading brain: {}", e);
-                        None
+                        ev_notification.send(NotificationEvent {
+                            message: format!("Load failed: {e}"),
+                            is_error: true,
+                        });
+                        return;
                     }
-                }) else {
-                    dbg!("Failed to reconstruct brain from reflection");
-                    return;
                 };
+                ev_notification.send(NotificationEvent {
+                    message: format!(
+                        "Loaded gen {}, fitness {:.0}",
+                        brain.metadata.generation, brain.metadata.fitness
+                    ),
+                    is_error: false,
+                });
                 commands.insert_resource(State::new(AppState::LoadingNetwork));
                 ev_load_network.send(LoadNetworkEvent(brain));
             }
@@ -128,6 +168,138 @@ pub fn load_handler(
     }
 }
 
+/// Picks the next brain to load: the latest, the best, then every saved brain from the
+/// most recent one
+pub fn browse_handler(
+    mut interaction_q: Query<(&Interaction, &mut BorderColor), query_filters::BrowseButton>,
+    load_button_q: Query<&Children, With<LoadButton>>,
+    mut text_q: Query<&mut Text>,
+    mut selection: ResMut<LoadSelection>,
+) {
+    for (interaction, mut border_color) in &mut interaction_q {
+        match interaction {
+            Interaction::Pressed => {
+                border_color.0 = Color::YELLOW_GREEN;
+                let library: Vec<BrainSelection> = list_brains(BRAINS_DIR)
+                    .into_iter()
+                    .map(|entry| BrainSelection::File(entry.path))
+                    .collect();
+                let choices: Vec<BrainSelection> = [BrainSelection::Latest, BrainSelection::Best]
+                    .into_iter()
+                    .chain(library)
+                    .collect();
+                let current = choices.iter().position(|choice| *choice == selection.0);
+                selection.0 = choices[current.map_or(0, |i| (i + 1) % choices.len())].clone();
+
+                for children in &load_button_q {
+                    for child in children {
+                        if let Ok(mut text) = text_q.get_mut(*child) {
+                            text.sections[0].value = format!("Load {}", selection.0);
+                        }
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::CYAN;
+            }
+            Interaction::None => {
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+/// Cycles the saves through RON, the compact binary encoding and the portable JSON
+pub fn encoding_handler(
+    mut interaction_q: Query<
+        (&Interaction, &mut BorderColor, &Children),
+        query_filters::EncodingButton,
+    >,
+    mut text_q: Query<&mut Text>,
+    mut encoding: ResMut<SaveEncoding>,
+) {
+    for (interaction, mut border_color, children) in &mut interaction_q {
+        match interaction {
+            Interaction::Pressed => {
+                border_color.0 = Color::YELLOW_GREEN;
+                let current = BrainEncoding::ALL.iter().position(|e| *e == encoding.0);
+                encoding.0 =
+                    BrainEncoding::ALL[current.map_or(0, |i| (i + 1) % BrainEncoding::ALL.len())];
+                for child in children {
+                    if let Ok(mut text) = text_q.get_mut(*child) {
+                        text.sections[0].value = encoding.0.to_string();
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                border_color.0 = Color::CYAN;
+            }
+            Interaction::None => {
+                border_color.0 = Color::BLACK;
+            }
+        }
+    }
+}
+
+/// Shows every notification as a toast for a few seconds
+pub fn show_notifications(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    toast_area_q: Query<Entity, With<ToastArea>>,
+    mut ev_notification: EventReader<NotificationEvent>,
+) {
+    let Ok(toast_area) = toast_area_q.get_single() else {
+        return;
+    };
+    let font: Handle<Font> = asset_server.load("fonts/FiraMono-Medium.ttf");
+    for notification in ev_notification.iter() {
+        if notification.is_error {
+            warn!("{}", notification.message);
+        }
+        let toast = commands
+            .spawn((
+                Toast(Timer::from_seconds(4.0, TimerMode::Once)),
+                NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(6.0)),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(if notification.is_error {
+                        Color::rgb(0.75, 0.15, 0.15)
+                    } else {
+                        Color::rgb(0.15, 0.5, 0.2)
+                    }),
+                    ..default()
+                },
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    notification.message.clone(),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            })
+            .id();
+        commands.entity(toast_area).add_child(toast);
+    }
+}
+
+pub fn expire_notifications(
+    mut commands: Commands,
+    mut toasts_q: Query<(Entity, &mut Toast)>,
+    time: Res<Time>,
+) {
+    for (toast_id, mut toast) in &mut toasts_q {
+        // Toasts keep expiring while the simulation is paused
+        if toast.0.tick(time.raw_delta()).finished() {
+            commands.entity(toast_id).despawn_recursive();
+        }
+    }
+}
+
 fn new_button(width: f32) -> ButtonBundle {
     ButtonBundle {
         style: Style {
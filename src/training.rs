@@ -0,0 +1,487 @@
+//! Supervised training of the networks by backpropagation, fitting them to the controls
+//! recorded while a human drove so evolution can start from a decent driver
+
+use crate::brain::{Brain, BrainMetadata};
+use crate::components::{NetworkError, NetworkLevel, NeuralNetwork};
+use crate::dataset::Dataset;
+use crate::resources::NetworkConfig;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fmt;
+
+const BETA1: f32 = 0.9;
+const BETA2: f32 = 0.999;
+const EPSILON: f32 = 1e-8;
+
+/// Settings of a training run
+#[derive(Debug, Clone)]
+pub struct TrainingConfig {
+    pub epochs: u32,
+    /// Samples averaged into every update of the weights
+    pub batch_size: usize,
+    pub optimizer: Optimizer,
+    /// Step size of the optimizer
+    pub learning_rate: f32,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        let optimizer = Optimizer::default();
+        Self {
+            epochs: 50,
+            batch_size: 32,
+            optimizer,
+            learning_rate: optimizer.default_learning_rate(),
+        }
+    }
+}
+
+/// How the gradients averaged over a batch update the weights
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Optimizer {
+    /// Plain gradient descent, every weight moving by the same multiple of its gradient
+    Sgd,
+    /// Gradient descent with a step adapted to every weight from running averages of its
+    /// gradients
+    #[default]
+    Adam,
+}
+
+impl Optimizer {
+    pub fn default_learning_rate(self) -> f32 {
+        match self {
+            Optimizer::Sgd => 0.1,
+            Optimizer::Adam => 0.003,
+        }
+    }
+}
+
+/// Fits the network to the dataset and wraps it into a brain that can be saved and loaded
+/// as any other. `network` gives the shape of the network and is checked against the
+/// dataset, its levels are randomly initialized unless `levels` are given.
+pub fn train_brain(
+    dataset: &Dataset,
+    network: NetworkConfig,
+    levels: Option<Vec<NetworkLevel>>,
+    config: &TrainingConfig,
+    seed: u64,
+    rng: &mut impl Rng,
+    on_epoch: impl FnMut(u32, f32),
+) -> Result<Brain, TrainingError> {
+    let mut levels = levels.unwrap_or_else(|| {
+        let activations = (network.hidden_activation, network.output_activation);
+        NeuralNetwork::new(&network.layers(), Vec::new(), activations, rng).levels
+    });
+    train(&mut levels, dataset, config, rng, on_epoch)?;
+    Ok(Brain {
+        metadata: BrainMetadata::now(
+            0,
+            0.,
+            0.,
+            seed,
+            NetworkConfig {
+                input_ray_length: dataset.ray_length,
+                input_ray_spread: dataset.ray_spread,
+                ..network
+            },
+        ),
+        levels,
+    })
+}
+
+/// Minimizes the mean squared error between the outputs of the levels and the recorded
+/// controls with mini-batch SGD or Adam, reporting the mean loss of every epoch. Returns the loss
+/// of the last epoch.
+pub fn train(
+    levels: &mut [NetworkLevel],
+    dataset: &Dataset,
+    config: &TrainingConfig,
+    rng: &mut impl Rng,
+    mut on_epoch: impl FnMut(u32, f32),
+) -> Result<f32, TrainingError> {
+    NeuralNetwork::validate(levels).map_err(TrainingError::InvalidNetwork)?;
+    if dataset.samples.is_empty() {
+        return Err(TrainingError::EmptyDataset);
+    }
+    if let Some(level) = levels
+        .iter()
+        .position(|l| !l.activation.is_differentiable())
+    {
+        return Err(TrainingError::NotDifferentiable { level });
+    }
+    let (inputs, outputs) = (
+        levels[0].inputs.len(),
+        levels[levels.len() - 1].outputs.len(),
+    );
+    if inputs != dataset.rays() {
+        return Err(TrainingError::InputMismatch {
+            inputs,
+            rays: dataset.rays(),
+        });
+    }
+    if outputs != 4 {
+        return Err(TrainingError::OutputMismatch { outputs });
+    }
+
+    let samples: Vec<(&[f32], [f32; 4])> = dataset
+        .samples
+        .iter()
+        .map(|sample| (sample.rays.as_slice(), sample.targets()))
+        .collect();
+    let mut order: Vec<usize> = (0..samples.len()).collect();
+    let mut adam = Adam::new(levels);
+    let mut gradients: Vec<Gradients> = levels.iter().map(Gradients::zeros).collect();
+    let mut epoch_loss = 0.;
+
+    for epoch in 0..config.epochs {
+        order.shuffle(rng);
+        let mut loss_sum = 0.;
+        for batch in order.chunks(config.batch_size.max(1)) {
+            gradients.iter_mut().for_each(Gradients::clear);
+            for &index in batch {
+                let (inputs, targets) = &samples[index];
+                loss_sum += backpropagate(levels, inputs, targets, &mut gradients);
+            }
+            match config.optimizer {
+                Optimizer::Sgd => sgd_step(levels, &gradients, batch.len(), config.learning_rate),
+                Optimizer::Adam => {
+                    adam.step(levels, &gradients, batch.len(), config.learning_rate);
+                }
+            }
+        }
+        epoch_loss = loss_sum / samples.len() as f32;
+        on_epoch(epoch, epoch_loss);
+    }
+    Ok(epoch_loss)
+}
+
+/// Adds the gradients of the squared error of a single sample, returning that error
+fn backpropagate(
+    levels: &mut [NetworkLevel],
+    inputs: &[f32],
+    targets: &[f32],
+    gradients: &mut [Gradients],
+) -> f32 {
+    let mut outputs = inputs.to_vec();
+    for level in levels.iter_mut() {
+        outputs = level.feed_forward(&outputs).clone();
+    }
+    let count = outputs.len() as f32;
+    let loss = outputs
+        .iter()
+        .zip(targets)
+        .map(|(y, t)| (y - t).powi(2))
+        .sum::<f32>()
+        / count;
+
+    // Gradient of the loss with respect to the outputs of the current level
+    let mut delta: Vec<f32> = outputs
+        .iter()
+        .zip(targets)
+        .map(|(y, t)| 2. * (y - t) / count)
+        .collect();
+    for (level, gradients) in levels.iter().zip(gradients.iter_mut()).rev() {
+        level.activation.backpropagate(&level.outputs, &mut delta);
+        let mut input_delta = vec![0.; level.inputs.len()];
+        for (i, input) in level.inputs.iter().enumerate() {
+            for (o, d) in delta.iter().enumerate() {
+                gradients.weights[i][o] += input * d;
+                input_delta[i] += level.weights[i][o] * d;
+            }
+        }
+        // Biases are subtracted from the weighted sums
+        for (bias, d) in gradients.biases.iter_mut().zip(&delta) {
+            *bias -= d;
+        }
+        delta = input_delta;
+    }
+    loss
+}
+
+/// A value for every weight and bias of a level
+#[derive(Clone)]
+struct Gradients {
+    weights: Vec<Vec<f32>>,
+    biases: Vec<f32>,
+}
+
+impl Gradients {
+    fn zeros(level: &NetworkLevel) -> Self {
+        Self {
+            weights: vec![vec![0.; level.outputs.len()]; level.inputs.len()],
+            biases: vec![0.; level.outputs.len()],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.weights.iter_mut().flatten().for_each(|w| *w = 0.);
+        self.biases.iter_mut().for_each(|b| *b = 0.);
+    }
+}
+
+/// Updates the levels with the gradients summed over a batch of `batch_len` samples
+fn sgd_step(
+    levels: &mut [NetworkLevel],
+    gradients: &[Gradients],
+    batch_len: usize,
+    learning_rate: f32,
+) {
+    let step = learning_rate / batch_len as f32;
+    for (level, gradients) in levels.iter_mut().zip(gradients) {
+        let values = level.weights.iter_mut().flatten().chain(&mut level.biases);
+        let gradients = gradients.weights.iter().flatten().chain(&gradients.biases);
+        for (value, gradient) in values.zip(gradients) {
+            *value -= step * gradient;
+        }
+    }
+}
+
+/// Running averages of the gradients and of their squares
+struct Adam {
+    first: Vec<Gradients>,
+    second: Vec<Gradients>,
+    steps: i32,
+}
+
+impl Adam {
+    fn new(levels: &[NetworkLevel]) -> Self {
+        let zeros: Vec<Gradients> = levels.iter().map(Gradients::zeros).collect();
+        Self {
+            first: zeros.clone(),
+            second: zeros,
+            steps: 0,
+        }
+    }
+
+    /// Updates the levels with the gradients summed over a batch of `batch_len` samples
+    fn step(
+        &mut self,
+        levels: &mut [NetworkLevel],
+        gradients: &[Gradients],
+        batch_len: usize,
+        learning_rate: f32,
+    ) {
+        self.steps += 1;
+        let corrections = (1. - BETA1.powi(self.steps), 1. - BETA2.powi(self.steps));
+        let update = |value: &mut f32, gradient: f32, first: &mut f32, second: &mut f32| {
+            let gradient = gradient / batch_len as f32;
+            *first = BETA1 * *first + (1. - BETA1) * gradient;
+            *second = BETA2 * *second + (1. - BETA2) * gradient * gradient;
+            let (first, second) = (*first / corrections.0, *second / corrections.1);
+            *value -= learning_rate * first / (second.sqrt() + EPSILON);
+        };
+
+        for (((level, gradients), first), second) in levels
+            .iter_mut()
+            .zip(gradients)
+            .zip(&mut self.first)
+            .zip(&mut self.second)
+        {
+            for (i, weights) in level.weights.iter_mut().enumerate() {
+                for (o, weight) in weights.iter_mut().enumerate() {
+                    update(
+                        weight,
+                        gradients.weights[i][o],
+                        &mut first.weights[i][o],
+                        &mut second.weights[i][o],
+                    );
+                }
+            }
+            for (o, bias) in level.biases.iter_mut().enumerate() {
+                update(
+                    bias,
+                    gradients.biases[o],
+                    &mut first.biases[o],
+                    &mut second.biases[o],
+                );
+            }
+        }
+    }
+}
+
+/// Reasons a network can't be trained on a dataset
+#[derive(Debug)]
+pub enum TrainingError {
+    InvalidNetwork(NetworkError),
+    EmptyDataset,
+    /// The step activation of the level has no gradient to follow
+    NotDifferentiable {
+        level: usize,
+    },
+    InputMismatch {
+        inputs: usize,
+        rays: usize,
+    },
+    /// The network doesn't output the 4 controls
+    OutputMismatch {
+        outputs: usize,
+    },
+}
+
+impl fmt::Display for TrainingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrainingError::InvalidNetwork(e) => write!(f, "invalid network: {e}"),
+            TrainingError::EmptyDataset => write!(f, "the dataset has no sample"),
+            TrainingError::NotDifferentiable { level } => write!(
+                f,
+                "level {level} uses the step activation, which can't be trained"
+            ),
+            TrainingError::InputMismatch { inputs, rays } => write!(
+                f,
+                "the network has {inputs} inputs but the dataset was recorded with {rays} rays"
+            ),
+            TrainingError::OutputMismatch { outputs } => {
+                write!(f, "the network has {outputs} outputs instead of 4")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TrainingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Activation;
+    use crate::dataset::Sample;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn levels(hidden: Activation, output: Activation, rng: &mut StdRng) -> Vec<NetworkLevel> {
+        NeuralNetwork::new(&[5, 6, 4], Vec::new(), (hidden, output), rng).levels
+    }
+
+    /// Error of the levels on a sample, without keeping the gradients
+    fn loss(levels: &mut [NetworkLevel], inputs: &[f32], targets: &[f32]) -> f32 {
+        let mut scratch: Vec<Gradients> = levels.iter().map(Gradients::zeros).collect();
+        backpropagate(levels, inputs, targets, &mut scratch)
+    }
+
+    /// Weight from input `row` to output `o`, or the bias of `o` without a row
+    fn parameter(level: &mut NetworkLevel, row: Option<usize>, o: usize) -> &mut f32 {
+        match row {
+            Some(i) => &mut level.weights[i][o],
+            None => &mut level.biases[o],
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        const H: f32 = 1e-3;
+        let mut rng = StdRng::seed_from_u64(11);
+        let inputs: Vec<f32> = (0..5).map(|_| rng.gen_range(-1.0..1.)).collect();
+        let targets = [1., 0., 0., 1.];
+        let activations = [
+            (Activation::Tanh, Activation::Sigmoid),
+            (Activation::LeakyRelu, Activation::Softmax),
+            (Activation::Relu, Activation::Linear),
+            (Activation::Sigmoid, Activation::Tanh),
+            (Activation::LeakyRelu, Activation::LeakyRelu),
+        ];
+        for (hidden, output) in activations {
+            let mut levels = levels(hidden, output, &mut rng);
+            let mut gradients: Vec<Gradients> = levels.iter().map(Gradients::zeros).collect();
+            backpropagate(&mut levels, &inputs, &targets, &mut gradients);
+
+            for l in 0..levels.len() {
+                let (rows, columns) = (levels[l].inputs.len(), levels[l].outputs.len());
+                let parameters = (0..rows)
+                    .flat_map(|i| (0..columns).map(move |o| (Some(i), o)))
+                    .chain((0..columns).map(|o| (None, o)));
+                for (row, o) in parameters {
+                    let original = *parameter(&mut levels[l], row, o);
+                    *parameter(&mut levels[l], row, o) = original + H;
+                    let above = loss(&mut levels, &inputs, &targets);
+                    *parameter(&mut levels[l], row, o) = original - H;
+                    let below = loss(&mut levels, &inputs, &targets);
+                    *parameter(&mut levels[l], row, o) = original;
+
+                    let numeric = (above - below) / (2. * H);
+                    let analytic = match row {
+                        Some(i) => gradients[l].weights[i][o],
+                        None => gradients[l].biases[o],
+                    };
+                    assert!(
+                        (numeric - analytic).abs() <= 1e-3 + 0.02 * numeric.abs(),
+                        "{hidden:?}/{output:?} level {l} {row:?},{o}: \
+                         {analytic} instead of {numeric}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leaky_relu_keeps_a_small_gradient_below_zero() {
+        let mut gradients = [1., 1.];
+        Activation::LeakyRelu.backpropagate(&[-0.02, 3.], &mut gradients);
+        assert_eq!(gradients, [0.01, 1.]);
+    }
+
+    #[test]
+    fn softmax_gradients_sum_to_zero() {
+        // The outputs always sum to 1, so no change of the sums can move them all one way
+        let mut gradients = [0.3, -1.2, 0.7, 2.];
+        Activation::Softmax.backpropagate(&[0.1, 0.2, 0.3, 0.4], &mut gradients);
+        assert!(gradients.iter().sum::<f32>().abs() < 1e-6);
+    }
+
+    fn separable_dataset() -> Dataset {
+        let sample = |rays: [f32; 5], acceleration| Sample {
+            speed: 0.,
+            rays: rays.to_vec(),
+            acceleration,
+            turn_direction: 0.,
+        };
+        Dataset {
+            ray_length: 100.,
+            ray_spread: 1.,
+            samples: vec![
+                // Nothing ahead, accelerate
+                sample([-1., -1., -1., -1., -1.], 1.),
+                sample([-1., -0.8, -1., -0.9, -1.], 1.),
+                // Something close ahead, brake
+                sample([-1., 0.6, 0.9, 0.5, -1.], -1.),
+                sample([-0.8, 0.8, 0.7, 0.9, -0.7], -1.),
+            ],
+        }
+    }
+
+    #[test]
+    fn learns_a_separable_dataset() {
+        let dataset = separable_dataset();
+        for optimizer in [Optimizer::Sgd, Optimizer::Adam] {
+            let mut rng = StdRng::seed_from_u64(5);
+            let mut levels = levels(Activation::Tanh, Activation::Sigmoid, &mut rng);
+            let config = TrainingConfig {
+                epochs: 500,
+                batch_size: 2,
+                optimizer,
+                learning_rate: optimizer.default_learning_rate(),
+            };
+            let mut first_loss = None;
+            let last_loss = train(&mut levels, &dataset, &config, &mut rng, |_, loss| {
+                first_loss.get_or_insert(loss);
+            })
+            .unwrap();
+            assert!(
+                last_loss < first_loss.unwrap() / 4.,
+                "{optimizer:?}: {first_loss:?} down to {last_loss}"
+            );
+
+            for sample in &dataset.samples {
+                let mut outputs = sample.rays.clone();
+                for level in levels.iter_mut() {
+                    outputs = level.feed_forward(&outputs).clone();
+                }
+                let (accelerate, brake) = (outputs[0] > 0.5, outputs[3] > 0.5);
+                assert_eq!(
+                    (accelerate, brake),
+                    (sample.acceleration > 0., sample.acceleration < 0.),
+                    "{optimizer:?}"
+                );
+            }
+        }
+    }
+}
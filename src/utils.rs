@@ -1,4 +1,8 @@
+use bevy::prelude::{Transform, Vec2};
+use std::fs::{File, OpenOptions};
+use std::io;
 use std::ops::{Add, Mul, Sub};
+use std::path::{Path, PathBuf};
 
 /// Requires two generics, the first one is the parameters type and the second the return type
 pub(super) fn lerp<U, T>(a: U, b: U, t: U) -> T
@@ -12,3 +16,107 @@ where
 {
     T::from(a + (b - a) * t)
 }
+
+/// Corners of a rectangle of the given size centered on the transform and following its
+/// rotation, in winding order
+pub(super) fn rect_corners(xform: &Transform, size: Vec2) -> [Vec2; 4] {
+    let half = size / 2.;
+    [
+        Vec2::new(-half.x, -half.y),
+        Vec2::new(-half.x, half.y),
+        Vec2::new(half.x, half.y),
+        Vec2::new(half.x, -half.y),
+    ]
+    .map(|corner| (xform.translation + xform.rotation * corner.extend(0.)).truncate())
+}
+
+/// Separating axis test between two rotated rectangles given by their corners
+pub(super) fn rects_overlap(a: &[Vec2; 4], b: &[Vec2; 4]) -> bool {
+    // The edges of a rectangle only have two distinct normals
+    let axes = [a[1] - a[0], a[3] - a[0], b[1] - b[0], b[3] - b[0]];
+    axes.iter().all(|axis| {
+        let (a_min, a_max) = project(a, *axis);
+        let (b_min, b_max) = project(b, *axis);
+        a_min <= b_max && b_min <= a_max
+    })
+}
+
+fn project(corners: &[Vec2; 4], axis: Vec2) -> (f32, f32) {
+    corners
+        .iter()
+        .map(|corner| corner.dot(axis))
+        .fold((f32::MAX, f32::MIN), |(min, max), p| {
+            (min.min(p), max.max(p))
+        })
+}
+
+/// Radius of the circle enclosing a rectangle of the given size
+pub(super) fn bounding_radius(size: Vec2) -> f32 {
+    (size / 2.).length()
+}
+
+/// Creates `<stem>.<extension>` in `dir`, or `<stem>-1.<extension>`, `<stem>-2.<extension>`
+/// and so on when the name is taken, so an earlier file is never overwritten
+pub(super) fn create_new_file(
+    dir: &Path,
+    stem: &str,
+    extension: &str,
+) -> io::Result<(PathBuf, File)> {
+    std::fs::create_dir_all(dir)?;
+    for suffix in 0u32.. {
+        let name = match suffix {
+            0 => format!("{stem}.{extension}"),
+            _ => format!("{stem}-{suffix}.{extension}"),
+        };
+        let path = dir.join(name);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "every file name is taken",
+    ))
+}
+
+/// Directory under the system temp dir that is removed when dropped, so a failing test
+/// doesn't leave its files behind
+#[cfg(test)]
+pub(crate) struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    /// Reserves `selfdriving-car-<name>-<pid>`, created by whatever writes to it first
+    pub(crate) fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("selfdriving-car-{name}-{}", std::process::id()));
+        // Left over by an earlier process with the same id
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}